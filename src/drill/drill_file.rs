@@ -0,0 +1,144 @@
+use gerber_types::Unit;
+
+use crate::layer::vec2::Vec2;
+
+/// Diameters closer than this are the same tool.
+const DIAMETER_TOLERANCE: f64 = 1e-4;
+
+/// Holes of a drill file grouped by tool, in millimetres whatever the unit of the file.
+#[derive(Debug, Default, Clone)]
+pub struct DrillFile {
+    pub unit: Option<Unit>,
+    pub tools: Vec<DrillTool>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DrillTool {
+    pub number: u32,
+    pub diameter: f64,
+    pub holes: Vec<DrillHole>,
+    pub slots: Vec<DrillSlot>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct DrillHole {
+    pub position: Vec2,
+    pub plated: bool,
+}
+
+/// Oval hole, from a G85 slot or a routed segment.
+#[derive(Debug, Clone, Copy)]
+pub struct DrillSlot {
+    pub start: Vec2,
+    pub end: Vec2,
+    pub width: f64,
+    pub plated: bool,
+}
+
+/// Which holes are machined, PTH and NPTH usually coming from separate files.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DrillSet {
+    #[default]
+    Both,
+    Plated,
+    NonPlated,
+}
+
+impl DrillSet {
+    fn contains(&self, plated: bool) -> bool {
+        match self {
+            DrillSet::Both => true,
+            DrillSet::Plated => plated,
+            DrillSet::NonPlated => !plated,
+        }
+    }
+}
+
+/// How slots are machined.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SlotStrategy {
+    /// Route a tool of the slot width from one end to the other.
+    #[default]
+    Mill,
+    /// Plunge a row of drills, each one overlapping the previous by half its diameter.
+    Drill,
+}
+
+impl DrillFile {
+    pub fn hole_count(&self) -> usize {
+        self.tools.iter().map(|tool| tool.holes.len()).sum()
+    }
+
+    pub fn slot_count(&self) -> usize {
+        self.tools.iter().map(|tool| tool.slots.len()).sum()
+    }
+
+    /// Add the tools of `other`, those of the same diameter sharing one tool.
+    pub fn merge(&mut self, other: DrillFile) {
+        self.unit = self.unit.or(other.unit);
+
+        for tool in other.tools {
+            match self
+                .tools
+                .iter_mut()
+                .find(|own| (own.diameter - tool.diameter).abs() < DIAMETER_TOLERANCE)
+            {
+                Some(own) => {
+                    own.holes.extend(tool.holes);
+                    own.slots.extend(tool.slots);
+                }
+                None => {
+                    let number = self.tools.iter().map(|own| own.number).max().unwrap_or(0) + 1;
+                    self.tools.push(DrillTool { number, ..tool });
+                }
+            }
+        }
+    }
+
+    /// Copy to machine: holes of `set` only, slots to drill become rows of holes of
+    /// their tool, and tools left empty are removed.
+    pub fn for_machining(&self, set: DrillSet, strategy: SlotStrategy) -> DrillFile {
+        let mut drill = self.clone();
+
+        for tool in drill.tools.iter_mut() {
+            tool.holes.retain(|hole| set.contains(hole.plated));
+            tool.slots.retain(|slot| set.contains(slot.plated));
+
+            if strategy == SlotStrategy::Drill {
+                for slot in tool.slots.drain(..) {
+                    tool.holes
+                        .extend(slot.drill_hits().into_iter().map(|position| DrillHole {
+                            position,
+                            plated: slot.plated,
+                        }));
+                }
+            }
+        }
+
+        drill
+            .tools
+            .retain(|tool| !tool.holes.is_empty() || !tool.slots.is_empty());
+        drill
+    }
+}
+
+impl DrillSlot {
+    /// Evenly spaced hits from `start` to `end`, at most half the width apart.
+    pub fn drill_hits(&self) -> Vec<Vec2> {
+        // Without width, the hits would never be close enough
+        if self.width <= 0.0 {
+            return vec![self.start, self.end];
+        }
+
+        let length = self.start.distance(&self.end);
+        let count = (length / (self.width / 2.0)).ceil().max(1.0) as usize;
+
+        (0..=count)
+            .map(|i| {
+                let mut step = self.end - self.start;
+                step.mult(i as f64 / count as f64);
+                self.start + step
+            })
+            .collect()
+    }
+}
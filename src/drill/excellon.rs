@@ -0,0 +1,433 @@
+use std::{collections::HashMap, io::BufRead};
+
+use gerber_types::Unit;
+use log::warn;
+
+use crate::layer::{error::LayerError, layer::MM_PER_INCH, vec2::Vec2};
+
+use super::drill_file::{DrillFile, DrillHole, DrillSlot, DrillTool};
+
+/// Which zeros are written in coordinates without a decimal point.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Zeros {
+    /// LZ: leading zeros are kept, trailing ones are omitted.
+    Leading,
+    /// TZ: trailing zeros are kept, leading ones are omitted.
+    Trailing,
+}
+
+/// What coordinates do: drill a hole, or move the router with the tool up (G00) or
+/// along a slot when plunged (G01).
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    Drill,
+    Rapid,
+    Route,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct NumberFormat {
+    integer: usize,
+    decimal: usize,
+    zeros: Zeros,
+}
+
+impl NumberFormat {
+    fn default_for(unit: Unit, zeros: Zeros) -> Self {
+        match unit {
+            Unit::Millimeters => Self {
+                integer: 3,
+                decimal: 3,
+                zeros,
+            },
+            Unit::Inches => Self {
+                integer: 2,
+                decimal: 4,
+                zeros,
+            },
+        }
+    }
+
+    fn parse(&self, text: &str) -> Option<f64> {
+        if text.contains('.') {
+            return text.parse().ok();
+        }
+
+        let (sign, digits) = match text.strip_prefix('-') {
+            Some(digits) => (-1.0, digits),
+            None => (1.0, text.strip_prefix('+').unwrap_or(text)),
+        };
+
+        if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+
+        let digits = match self.zeros {
+            Zeros::Trailing => digits.to_string(),
+            Zeros::Leading => format!("{digits:0<width$}", width = self.integer + self.decimal),
+        };
+
+        let value: f64 = digits.parse().ok()?;
+        Some(sign * value / 10f64.powi(self.decimal as i32))
+    }
+}
+
+/// Parse an Excellon drill file: tool table, units and number format from the M48
+/// header, then holes and slots machined with each tool. Holes are plated unless X2
+/// attributes in comments (as written by KiCad) say otherwise.
+pub fn parse_excellon<R: BufRead>(reader: R) -> Result<DrillFile, LayerError> {
+    let mut drill = DrillFile::default();
+
+    let mut unit = Unit::Millimeters;
+    let mut format: Option<NumberFormat> = None;
+    let mut zeros = Zeros::Trailing;
+    let mut header = false;
+    let mut incremental = false;
+    let mut current_pos = Vec2::default();
+    let mut current_tool: Option<usize> = None;
+    let mut mode = Mode::Drill;
+    let mut plunged = false;
+    let mut file_plated = true;
+    let mut next_tool_plated: Option<bool> = None;
+    let mut tool_plated: HashMap<u32, bool> = HashMap::new();
+
+    for (number, line) in reader.lines().enumerate() {
+        let line = line.map_err(|e| LayerError::Io(e.to_string()))?;
+        let line = line.trim();
+        let parse_error = |message: &str| LayerError::Parse {
+            line: Some((number + 1, line.to_string())),
+            message: message.to_string(),
+        };
+
+        if let Some(attribute) = line
+            .strip_prefix(';')
+            .and_then(|comment| comment.trim_start().strip_prefix("#@!"))
+        {
+            let attribute = attribute.trim();
+            let plated = if attribute.contains(",NonPlated") {
+                Some(false)
+            } else if attribute.contains(",Plated") {
+                Some(true)
+            } else {
+                None
+            };
+
+            if attribute.starts_with("TF.FileFunction") {
+                file_plated = plated.unwrap_or(file_plated);
+            } else if attribute.starts_with("TA.AperFunction") {
+                next_tool_plated = plated;
+            }
+            continue;
+        }
+
+        if line.is_empty() || line.starts_with(';') {
+            continue;
+        }
+
+        // Route commands may share their line with coordinates
+        let line = match ["G00", "G01", "G02", "G03"]
+            .into_iter()
+            .find(|code| line.starts_with(code))
+        {
+            Some(code) => {
+                if code == "G02" || code == "G03" {
+                    warn!("Circular routing is not supported, \"{line}\" is routed straight");
+                }
+                mode = if code == "G00" {
+                    Mode::Rapid
+                } else {
+                    Mode::Route
+                };
+                &line[code.len()..]
+            }
+            None => line,
+        };
+
+        if line.is_empty() {
+            continue;
+        }
+
+        let scale = match unit {
+            Unit::Millimeters => 1.0,
+            Unit::Inches => MM_PER_INCH,
+        };
+        let number_format = format.unwrap_or(NumberFormat::default_for(unit, zeros));
+
+        match line {
+            "M48" => header = true,
+            "%" | "M95" => header = false,
+            "M71" => unit = Unit::Millimeters,
+            "M72" => unit = Unit::Inches,
+            "G90" => incremental = false,
+            "G91" => incremental = true,
+            "G05" | "G81" => mode = Mode::Drill,
+            "M15" => plunged = true,
+            "M16" | "M17" => plunged = false,
+            "M30" | "M00" => break,
+            _ if line.starts_with("METRIC") || line.starts_with("INCH") => {
+                let mut fields = line.split(',');
+                unit = match fields.next() {
+                    Some("INCH") => Unit::Inches,
+                    _ => Unit::Millimeters,
+                };
+
+                for field in fields {
+                    match field {
+                        "LZ" => zeros = Zeros::Leading,
+                        "TZ" => zeros = Zeros::Trailing,
+                        _ => match field.split_once('.') {
+                            Some((integer, decimal)) => {
+                                format = Some(NumberFormat {
+                                    integer: integer.len(),
+                                    decimal: decimal.len(),
+                                    zeros,
+                                })
+                            }
+                            None => warn!("Unknown unit option \"{field}\""),
+                        },
+                    }
+                }
+
+                if let Some(format) = format.as_mut() {
+                    format.zeros = zeros;
+                }
+            }
+            "ICI,ON" => incremental = true,
+            "ICI,OFF" => incremental = false,
+            // Tool definition or selection, not TCST and other header commands
+            _ if line.starts_with('T') && line[1..].starts_with(|c: char| c.is_ascii_digit()) => {
+                let (code, parameters) = line[1..]
+                    .find(|c: char| !c.is_ascii_digit())
+                    .map_or((&line[1..], ""), |i| line[1..].split_at(i));
+                let code: u32 = code
+                    .parse()
+                    .map_err(|_| parse_error("Invalid tool number"))?;
+
+                // The diameter follows C, among other tool parameters (feed, speed...)
+                let diameter = match parameters.find('C') {
+                    Some(start) => {
+                        let value = &parameters[start + 1..];
+                        let end = value
+                            .find(|c: char| c.is_ascii_alphabetic())
+                            .unwrap_or(value.len());
+                        Some(
+                            value[..end]
+                                .parse::<f64>()
+                                .ok()
+                                .filter(|diameter| *diameter > 0.0)
+                                .ok_or_else(|| parse_error("Invalid tool diameter"))?
+                                * scale,
+                        )
+                    }
+                    None => None,
+                };
+
+                match diameter {
+                    Some(diameter) => {
+                        if let Some(plated) = next_tool_plated.take() {
+                            tool_plated.insert(code, plated);
+                        }
+
+                        match drill.tools.iter().position(|tool| tool.number == code) {
+                            Some(index) => drill.tools[index].diameter = diameter,
+                            None => drill.tools.push(DrillTool {
+                                number: code,
+                                diameter,
+                                holes: Vec::new(),
+                                slots: Vec::new(),
+                            }),
+                        }
+
+                        if !header {
+                            current_tool = drill.tools.iter().position(|tool| tool.number == code);
+                        }
+                    }
+                    None if header => warn!("Tool T{code} has no diameter"),
+                    None if code == 0 => current_tool = None,
+                    None => match drill.tools.iter().position(|tool| tool.number == code) {
+                        Some(index) => current_tool = Some(index),
+                        None => return Err(parse_error("Tool selected but never defined")),
+                    },
+                }
+            }
+            _ if line.starts_with('X') || line.starts_with('Y') => {
+                let read = |text: &str, from: Vec2| {
+                    read_position(text, from, &number_format, scale, incremental)
+                        .ok_or_else(|| parse_error("Invalid coordinate"))
+                };
+                let plated = current_tool
+                    .and_then(|index| tool_plated.get(&drill.tools[index].number))
+                    .copied()
+                    .unwrap_or(file_plated);
+                let tool = current_tool
+                    .map(|index| &mut drill.tools[index])
+                    .ok_or_else(|| parse_error("Hole drilled without a selected tool"));
+
+                match line.split_once("G85") {
+                    Some((start, end)) => {
+                        let start = read(start, current_pos)?;
+                        current_pos = read(end, start)?;
+                        let tool = tool?;
+                        tool.slots.push(DrillSlot {
+                            start,
+                            end: current_pos,
+                            width: tool.diameter,
+                            plated,
+                        });
+                    }
+                    None => {
+                        let position = read(line, current_pos)?;
+                        match mode {
+                            Mode::Drill => tool?.holes.push(DrillHole { position, plated }),
+                            Mode::Route if plunged => {
+                                let tool = tool?;
+                                tool.slots.push(DrillSlot {
+                                    start: current_pos,
+                                    end: position,
+                                    width: tool.diameter,
+                                    plated,
+                                });
+                            }
+                            Mode::Route | Mode::Rapid => (),
+                        }
+                        current_pos = position;
+                    }
+                }
+            }
+            _ if line.starts_with('G') || line.starts_with('M') => {
+                warn!("Unsupported Excellon command \"{line}\"")
+            }
+            // Other header lines (FMAT, VER, ATC...) don't change the holes
+            _ if header => (),
+            _ => warn!("Unknown Excellon line \"{line}\""),
+        }
+    }
+
+    drill.unit = Some(unit);
+    Ok(drill)
+}
+
+/// Position reached by a coordinate line from `from`, `None` if a value is invalid.
+fn read_position(
+    line: &str,
+    from: Vec2,
+    format: &NumberFormat,
+    scale: f64,
+    incremental: bool,
+) -> Option<Vec2> {
+    let (x, y) = split_coordinates(line);
+    let mut position = from;
+
+    for (value, axis) in [(x, &mut position.x), (y, &mut position.y)] {
+        if let Some(value) = value {
+            let value = format.parse(value)? * scale;
+            *axis = if incremental { *axis + value } else { value };
+        }
+    }
+
+    Some(position)
+}
+
+/// X and Y values of a coordinate line, each one optional.
+fn split_coordinates(line: &str) -> (Option<&str>, Option<&str>) {
+    let x_start = line.find('X');
+    let y_start = line.find('Y');
+
+    let x = x_start.map(|start| match y_start {
+        Some(end) if end > start => &line[start + 1..end],
+        _ => &line[start + 1..],
+    });
+    let y = y_start.map(|start| match x_start {
+        Some(end) if end > start => &line[start + 1..end],
+        _ => &line[start + 1..],
+    });
+
+    (x, y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(unit: Unit, zeros: Zeros) -> NumberFormat {
+        NumberFormat::default_for(unit, zeros)
+    }
+
+    #[test]
+    fn inch_2_4_leading_zeros() {
+        let format = format(Unit::Inches, Zeros::Leading);
+        assert_eq!(format.parse("0125"), Some(1.25));
+        assert_eq!(format.parse("-0125"), Some(-1.25));
+        assert_eq!(format.parse("000050"), Some(0.005));
+    }
+
+    #[test]
+    fn inch_2_4_trailing_zeros() {
+        let format = format(Unit::Inches, Zeros::Trailing);
+        assert_eq!(format.parse("12500"), Some(1.25));
+        assert_eq!(format.parse("+50"), Some(0.005));
+    }
+
+    #[test]
+    fn metric_3_3_leading_zeros() {
+        let format = format(Unit::Millimeters, Zeros::Leading);
+        assert_eq!(format.parse("00125"), Some(1.25));
+        assert_eq!(format.parse("12345"), Some(123.45));
+    }
+
+    #[test]
+    fn metric_3_3_trailing_zeros() {
+        let format = format(Unit::Millimeters, Zeros::Trailing);
+        assert_eq!(format.parse("1250"), Some(1.25));
+        assert_eq!(format.parse("-5"), Some(-0.005));
+    }
+
+    #[test]
+    fn explicit_decimal_point_and_invalid_values() {
+        let format = format(Unit::Millimeters, Zeros::Leading);
+        assert_eq!(format.parse("1.25"), Some(1.25));
+        assert_eq!(format.parse(""), None);
+        assert_eq!(format.parse("12a"), None);
+    }
+
+    #[test]
+    fn split_coordinates_either_order() {
+        assert_eq!(split_coordinates("X100Y200"), (Some("100"), Some("200")));
+        assert_eq!(split_coordinates("Y200X100"), (Some("100"), Some("200")));
+        assert_eq!(split_coordinates("Y200"), (None, Some("200")));
+    }
+
+    #[test]
+    fn g85_slot() {
+        let file = "M48\nMETRIC,TZ\nT1C1.000\n%\nT1\nX1000Y2000G85X5000Y2000\nX7000Y0\nM30\n";
+        let drill = parse_excellon(file.as_bytes()).unwrap();
+
+        let tool = &drill.tools[0];
+        assert_eq!(tool.diameter, 1.0);
+        assert_eq!(tool.slots.len(), 1);
+        let slot = &tool.slots[0];
+        assert_eq!((slot.start.x, slot.start.y), (1.0, 2.0));
+        assert_eq!((slot.end.x, slot.end.y), (5.0, 2.0));
+        assert_eq!(slot.width, 1.0);
+
+        // The slot end is the current position afterwards, not its start
+        assert_eq!(tool.holes.len(), 1);
+        assert_eq!(
+            (tool.holes[0].position.x, tool.holes[0].position.y),
+            (7.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn zero_diameter_tool() {
+        let file = "M48\nMETRIC,TZ\nT1C0\n%\nT1\nX1000Y2000G85X5000Y2000\nM30\n";
+        assert!(parse_excellon(file.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn tool_commands_other_than_definitions() {
+        let file = "M48\nMETRIC,TZ\nTCST,OFF\nT1C1.000\n%\nT1\nX1000Y2000\nM30\n";
+        let drill = parse_excellon(file.as_bytes()).unwrap();
+        assert_eq!(drill.hole_count(), 1);
+    }
+}
@@ -0,0 +1,142 @@
+use gerber_parser::GerberDoc;
+use gerber_types::{
+    Aperture, Command, CoordinateMode, DCode, ExtendedCode, FileAttribute, FileFunction,
+    FunctionCode, GCode, InterpolationMode, Operation,
+};
+use log::warn;
+
+use crate::layer::{error::LayerError, layer::Layer, vec2::Vec2};
+
+use super::drill_file::{DrillFile, DrillHole, DrillSlot, DrillTool};
+
+/// Read a Gerber X2 drill file: each flash is a hole of the aperture diameter and each
+/// draw a slot. Holes are plated unless the file function says otherwise.
+pub fn drill_from_gerber(gerber: &GerberDoc) -> Result<DrillFile, LayerError> {
+    let mut drill = DrillFile {
+        unit: Layer::gerber_unit(gerber),
+        ..Default::default()
+    };
+
+    let scale = Layer::gerber_scale(gerber);
+    let mut incremental = Layer::gerber_incremental(gerber);
+    let mut plated = true;
+    let mut current_pos = Vec2::default();
+    let mut current_tool: Option<usize> = None;
+    let mut current_mode = InterpolationMode::Linear;
+    let mut region_mode = false;
+
+    for (index, cmd) in gerber.commands.iter().enumerate() {
+        let Some(cmd) = Layer::command(cmd)? else {
+            continue;
+        };
+
+        match cmd {
+            Command::ExtendedCode(ExtendedCode::FileAttribute(FileAttribute::FileFunction(
+                function,
+            ))) => match function {
+                FileFunction::Plated { .. } => plated = true,
+                FileFunction::NonPlated { .. } => plated = false,
+                _ => warn!("Not a drill file function: {function:?}"),
+            },
+            Command::FunctionCode(FunctionCode::DCode(DCode::SelectAperture(id))) => {
+                let diameter = match gerber.apertures.get(id) {
+                    Some(Aperture::Circle(circle)) if circle.diameter > 0.0 => {
+                        circle.diameter * scale
+                    }
+                    Some(Aperture::Circle(circle)) => {
+                        return Err(LayerError::Parse {
+                            line: None,
+                            message: format!(
+                                "Drill aperture D{id} has an invalid diameter: {}",
+                                circle.diameter
+                            ),
+                        })
+                    }
+                    Some(aperture) => {
+                        return Err(LayerError::UnsupportedCommand {
+                            index,
+                            command: format!("Drill aperture D{id} is not a circle: {aperture:?}"),
+                        })
+                    }
+                    None => {
+                        return Err(LayerError::MissingAperture {
+                            index,
+                            code: Some(*id),
+                        })
+                    }
+                };
+
+                let number = *id as u32;
+                current_tool = match drill.tools.iter().position(|tool| tool.number == number) {
+                    Some(index) => Some(index),
+                    None => {
+                        drill.tools.push(DrillTool {
+                            number,
+                            diameter,
+                            holes: Vec::new(),
+                            slots: Vec::new(),
+                        });
+                        Some(drill.tools.len() - 1)
+                    }
+                };
+            }
+            Command::FunctionCode(FunctionCode::DCode(DCode::Operation(operation))) => {
+                let mut target = current_pos;
+                let coordinates = match operation {
+                    Operation::Move(coordinates)
+                    | Operation::Interpolate(coordinates, _)
+                    | Operation::Flash(coordinates) => coordinates,
+                };
+                target.set(coordinates, scale, incremental);
+
+                // The draws of a region are its contour, not routes
+                let drills = match operation {
+                    Operation::Move(_) => false,
+                    Operation::Interpolate(..) => !region_mode,
+                    Operation::Flash(_) => true,
+                };
+
+                if drills {
+                    let Some(tool) = current_tool.map(|index| &mut drill.tools[index]) else {
+                        return Err(LayerError::MissingAperture { index, code: None });
+                    };
+
+                    if let Operation::Flash(_) = operation {
+                        tool.holes.push(DrillHole {
+                            position: target,
+                            plated,
+                        });
+                    } else {
+                        if current_mode != InterpolationMode::Linear {
+                            warn!("Circular routing is not supported, command #{index} is routed straight");
+                        }
+                        tool.slots.push(DrillSlot {
+                            start: current_pos,
+                            end: target,
+                            width: tool.diameter,
+                            plated,
+                        });
+                    }
+                }
+
+                current_pos = target;
+            }
+            Command::FunctionCode(FunctionCode::GCode(GCode::InterpolationMode(mode))) => {
+                current_mode = *mode
+            }
+            Command::FunctionCode(FunctionCode::GCode(GCode::CoordinateMode(mode))) => {
+                incremental = *mode == CoordinateMode::Incremental
+            }
+            Command::FunctionCode(FunctionCode::GCode(GCode::RegionMode(region))) => {
+                if *region {
+                    warn!("Regions are not holes, the region from command #{index} is ignored");
+                }
+                region_mode = *region;
+            }
+            // Other attributes, comments, units... don't change the holes
+            _ => (),
+        }
+    }
+
+    Ok(drill)
+}
@@ -0,0 +1,3 @@
+pub mod drill_file;
+pub mod excellon;
+pub mod gerber_drill;
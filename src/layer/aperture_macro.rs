@@ -0,0 +1,481 @@
+use std::collections::HashMap;
+use std::f64::consts::TAU;
+
+use clipper2::{FillRule, Path, Paths, Point};
+use gerber_types::{ApertureMacro, MacroBoolean, MacroContent, MacroDecimal, MacroInteger};
+use log::{error, warn};
+
+use super::layer::Layer;
+use super::vec2::Vec2;
+
+/// Evaluate an aperture macro (%AM) with the parameters of an aperture definition, and
+/// return its shape flashed at `center`. Macro dimensions are in file units, converted by
+/// `scale`.
+pub fn macro_paths(
+    aperture_macro: &ApertureMacro,
+    args: &[f64],
+    center: Vec2,
+    scale: f64,
+    arc_tolerance: f64,
+) -> Paths {
+    let placement = Placement {
+        origin: center,
+        scale,
+    };
+    // Primitives are built in file units
+    let arc_tolerance = arc_tolerance / scale;
+
+    let mut variables: HashMap<u32, f64> = args
+        .iter()
+        .enumerate()
+        .map(|(i, value)| (i as u32 + 1, *value))
+        .collect();
+    let mut shape = Paths::default();
+
+    for content in &aperture_macro.content {
+        let primitive = match content {
+            MacroContent::Comment(_) => continue,
+            MacroContent::VariableDefinition(definition) => {
+                match eval_expression(&definition.expression, &variables) {
+                    Ok(value) => {
+                        variables.insert(definition.number, value);
+                    }
+                    Err(e) => error!(
+                        "Macro '{}': failed to evaluate ${}. Error: {e}",
+                        aperture_macro.name, definition.number
+                    ),
+                }
+                continue;
+            }
+            _ => primitive_paths(content, &variables, &placement, arc_tolerance),
+        };
+
+        match primitive {
+            Ok((true, paths)) => shape = union(shape, paths),
+            Ok((false, paths)) => shape = difference(shape, paths),
+            Err(e) => error!("Macro '{}': {e}", aperture_macro.name),
+        }
+    }
+
+    shape
+}
+
+/// Position of the flashed macro and conversion of its units.
+struct Placement {
+    origin: Vec2,
+    scale: f64,
+}
+
+/// Exposure and shape of a single primitive, rotated around the macro origin then placed.
+fn primitive_paths(
+    content: &MacroContent,
+    vars: &HashMap<u32, f64>,
+    placement: &Placement,
+    arc_tolerance: f64,
+) -> Result<(bool, Paths), String> {
+    let (exposure, polygons, angle): (bool, Vec<Vec<Vec2>>, f64) = match content {
+        MacroContent::Circle(circle) => {
+            let radius = decimal(&circle.diameter, vars)? / 2.0;
+            let center = point(&circle.center, vars)?;
+            let resolution = Layer::circle_resolution(radius, arc_tolerance);
+            let angle = match &circle.angle {
+                Some(angle) => decimal(angle, vars)?,
+                None => 0.0,
+            };
+
+            (
+                boolean(&circle.exposure, vars)?,
+                vec![regular_polygon(center, radius, resolution, 0.0)],
+                angle,
+            )
+        }
+        MacroContent::VectorLine(line) => {
+            let width = decimal(&line.width, vars)?;
+            let start = point(&line.start, vars)?;
+            let end = point(&line.end, vars)?;
+
+            let polygon = if start.distance(&end) > 0.0 {
+                let mut normal = Vec2::new_normalize(-(end.y - start.y), end.x - start.x);
+                normal.mult(width / 2.0);
+                let mut inv_normal = normal;
+                inv_normal.inv();
+
+                vec![
+                    start + normal,
+                    end + normal,
+                    end + inv_normal,
+                    start + inv_normal,
+                ]
+            } else {
+                Vec::new()
+            };
+
+            (
+                boolean(&line.exposure, vars)?,
+                vec![polygon],
+                decimal(&line.angle, vars)?,
+            )
+        }
+        MacroContent::CenterLine(line) => {
+            let width = decimal(&line.dimensions.0, vars)?;
+            let height = decimal(&line.dimensions.1, vars)?;
+            let center = point(&line.center, vars)?;
+
+            (
+                boolean(&line.exposure, vars)?,
+                vec![rectangle(center, width, height)],
+                decimal(&line.angle, vars)?,
+            )
+        }
+        MacroContent::Outline(outline) => {
+            let points = outline
+                .points
+                .iter()
+                .map(|p| point(p, vars))
+                .collect::<Result<Vec<Vec2>, String>>()?;
+
+            if points.len() < 4 {
+                return Err(format!(
+                    "outline needs at least 3 vertices, found {}",
+                    points.len().saturating_sub(1)
+                ));
+            }
+
+            (
+                boolean(&outline.exposure, vars)?,
+                vec![points],
+                decimal(&outline.angle, vars)?,
+            )
+        }
+        MacroContent::Polygon(polygon) => {
+            let vertices = integer(&polygon.vertices, vars)?;
+            if !(3..=12).contains(&vertices) {
+                return Err(format!(
+                    "polygon must have between 3 and 12 vertices, found {vertices}"
+                ));
+            }
+
+            (
+                boolean(&polygon.exposure, vars)?,
+                vec![regular_polygon(
+                    point(&polygon.center, vars)?,
+                    decimal(&polygon.diameter, vars)? / 2.0,
+                    vertices,
+                    0.0,
+                )],
+                decimal(&polygon.angle, vars)?,
+            )
+        }
+        MacroContent::Thermal(thermal) => {
+            let center = point(&thermal.center, vars)?;
+            let outer = decimal(&thermal.outer_diameter, vars)? / 2.0;
+            let inner = decimal(&thermal.inner_diameter, vars)? / 2.0;
+            let gap = decimal(&thermal.gap, vars)?;
+
+            if inner >= outer {
+                return Err("thermal inner diameter must be smaller than outer".to_string());
+            }
+
+            let angle = decimal(&thermal.angle, vars)?;
+            let resolution = Layer::circle_resolution(outer, arc_tolerance);
+            let ring = difference(
+                place(
+                    vec![regular_polygon(center, outer, resolution, 0.0)],
+                    angle,
+                    placement,
+                ),
+                place(
+                    vec![regular_polygon(center, inner, resolution, 0.0)],
+                    angle,
+                    placement,
+                ),
+            );
+            let cross = place(
+                vec![
+                    rectangle(center, outer * 2.0, gap),
+                    rectangle(center, gap, outer * 2.0),
+                ],
+                angle,
+                placement,
+            );
+
+            return Ok((true, difference(ring, cross)));
+        }
+        MacroContent::Moire(_) => return Err("moiré primitive is not supported".to_string()),
+        MacroContent::Comment(_) | MacroContent::VariableDefinition(_) => {
+            return Ok((true, Paths::default()))
+        }
+    };
+
+    Ok((exposure, place(polygons, angle, placement)))
+}
+
+fn regular_polygon(center: Vec2, radius: f64, vertices: u32, start_angle: f64) -> Vec<Vec2> {
+    let step = TAU / vertices as f64;
+
+    (0..vertices)
+        .map(|i| {
+            let angle = start_angle + step * i as f64;
+            Vec2::new(
+                center.x + radius * angle.cos(),
+                center.y + radius * angle.sin(),
+            )
+        })
+        .collect()
+}
+
+fn rectangle(center: Vec2, width: f64, height: f64) -> Vec<Vec2> {
+    let (w, h) = (width / 2.0, height / 2.0);
+
+    vec![
+        Vec2::new(center.x - w, center.y - h),
+        Vec2::new(center.x + w, center.y - h),
+        Vec2::new(center.x + w, center.y + h),
+        Vec2::new(center.x - w, center.y + h),
+    ]
+}
+
+/// Rotate counterclockwise around the macro origin by `degrees`, then scale and translate
+/// to the flash position.
+fn place(polygons: Vec<Vec<Vec2>>, degrees: f64, placement: &Placement) -> Paths {
+    let radians = degrees.to_radians();
+
+    Paths::new(
+        polygons
+            .into_iter()
+            .filter(|polygon| !polygon.is_empty())
+            .map(|polygon| {
+                let points: Vec<Point> = polygon
+                    .into_iter()
+                    .map(|p| {
+                        let mut p = p.rotated(radians);
+                        p.mult(placement.scale);
+                        (p + placement.origin).into()
+                    })
+                    .collect();
+                Path::new(points)
+            })
+            .collect(),
+    )
+}
+
+fn union(shape: Paths, paths: Paths) -> Paths {
+    match shape
+        .to_clipper_subject()
+        .add_clip(paths)
+        .union(FillRule::NonZero)
+    {
+        Ok(new) => new,
+        Err(e) => {
+            error!("Failed to union macro primitive. Error: {e}");
+            shape
+        }
+    }
+}
+
+fn difference(shape: Paths, paths: Paths) -> Paths {
+    match shape
+        .to_clipper_subject()
+        .add_clip(paths)
+        .difference(FillRule::NonZero)
+    {
+        Ok(new) => new,
+        Err(e) => {
+            error!("Failed to subtract macro primitive. Error: {e}");
+            shape
+        }
+    }
+}
+
+fn point(point: &(MacroDecimal, MacroDecimal), vars: &HashMap<u32, f64>) -> Result<Vec2, String> {
+    Ok(Vec2::new(
+        decimal(&point.0, vars)?,
+        decimal(&point.1, vars)?,
+    ))
+}
+
+fn decimal(value: &MacroDecimal, vars: &HashMap<u32, f64>) -> Result<f64, String> {
+    match value {
+        MacroDecimal::Value(value) => Ok(*value),
+        MacroDecimal::Variable(number) => Ok(variable(*number, vars)),
+        MacroDecimal::Expression(expression) => eval_expression(expression, vars),
+    }
+}
+
+fn boolean(value: &MacroBoolean, vars: &HashMap<u32, f64>) -> Result<bool, String> {
+    match value {
+        MacroBoolean::Value(value) => Ok(*value),
+        MacroBoolean::Variable(number) => Ok(variable(*number, vars) != 0.0),
+        MacroBoolean::Expression(expression) => Ok(eval_expression(expression, vars)? != 0.0),
+    }
+}
+
+fn integer(value: &MacroInteger, vars: &HashMap<u32, f64>) -> Result<u32, String> {
+    match value {
+        MacroInteger::Value(value) => Ok(*value),
+        MacroInteger::Variable(number) => Ok(variable(*number, vars).round() as u32),
+        MacroInteger::Expression(expression) => {
+            Ok(eval_expression(expression, vars)?.round() as u32)
+        }
+    }
+}
+
+/// Undefined variables are 0, as required by the Gerber specification.
+fn variable(number: u32, vars: &HashMap<u32, f64>) -> f64 {
+    match vars.get(&number) {
+        Some(value) => *value,
+        None => {
+            warn!("Macro variable ${number} is not defined, using 0");
+            0.0
+        }
+    }
+}
+
+/// Evaluate a macro arithmetic expression: decimal constants, `$n` variables, unary
+/// `+`/`-`, the operators `+`, `-`, `x` (or `X`), `/` and brackets.
+pub fn eval_expression(expression: &str, vars: &HashMap<u32, f64>) -> Result<f64, String> {
+    let mut parser = ExpressionParser {
+        chars: expression.chars().filter(|c| !c.is_whitespace()).collect(),
+        pos: 0,
+        vars,
+    };
+
+    let value = parser.sum()?;
+    match parser.peek() {
+        None => Ok(value),
+        Some(c) => Err(format!("unexpected '{c}' in expression '{expression}'")),
+    }
+}
+
+struct ExpressionParser<'a> {
+    chars: Vec<char>,
+    pos: usize,
+    vars: &'a HashMap<u32, f64>,
+}
+
+impl ExpressionParser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn sum(&mut self) -> Result<f64, String> {
+        let mut value = self.product()?;
+
+        while let Some(op @ ('+' | '-')) = self.peek() {
+            self.pos += 1;
+            let rhs = self.product()?;
+            value = if op == '+' { value + rhs } else { value - rhs };
+        }
+
+        Ok(value)
+    }
+
+    fn product(&mut self) -> Result<f64, String> {
+        let mut value = self.factor()?;
+
+        while let Some(op @ ('x' | 'X' | '/')) = self.peek() {
+            self.pos += 1;
+            let rhs = self.factor()?;
+            value = if op == '/' { value / rhs } else { value * rhs };
+        }
+
+        Ok(value)
+    }
+
+    fn factor(&mut self) -> Result<f64, String> {
+        match self.peek() {
+            Some('+') => {
+                self.pos += 1;
+                self.factor()
+            }
+            Some('-') => {
+                self.pos += 1;
+                Ok(-self.factor()?)
+            }
+            Some('(') => {
+                self.pos += 1;
+                let value = self.sum()?;
+                match self.peek() {
+                    Some(')') => {
+                        self.pos += 1;
+                        Ok(value)
+                    }
+                    _ => Err("missing ')' in expression".to_string()),
+                }
+            }
+            Some('$') => {
+                self.pos += 1;
+                let number = self.digits(false);
+                match number.parse::<u32>() {
+                    Ok(number) => Ok(variable(number, self.vars)),
+                    Err(_) => Err(format!("invalid variable '${number}'")),
+                }
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => {
+                let number = self.digits(true);
+                number
+                    .parse::<f64>()
+                    .map_err(|_| format!("invalid number '{number}'"))
+            }
+            Some(c) => Err(format!("unexpected '{c}' in expression")),
+            None => Err("unexpected end of expression".to_string()),
+        }
+    }
+
+    fn digits(&mut self, decimal: bool) -> String {
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            if c.is_ascii_digit() || (decimal && c == '.') {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+
+        self.chars[start..self.pos].iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(expression: &str, vars: &[(u32, f64)]) -> Result<f64, String> {
+        eval_expression(expression, &vars.iter().copied().collect())
+    }
+
+    #[test]
+    fn multiplication_before_subtraction() {
+        assert_eq!(eval("$1x2-(0.5/$2)", &[(1, 1.5), (2, 0.25)]), Ok(1.0));
+        assert_eq!(eval("1+2X3", &[]), Ok(7.0));
+        assert_eq!(eval("(1+2)x3", &[]), Ok(9.0));
+    }
+
+    #[test]
+    fn left_to_right_division() {
+        assert_eq!(eval("8/2/2", &[]), Ok(2.0));
+        assert_eq!(eval("8-2-2", &[]), Ok(4.0));
+    }
+
+    #[test]
+    fn unary_signs() {
+        assert_eq!(eval("-$1", &[(1, 2.5)]), Ok(-2.5));
+        assert_eq!(eval("--1", &[]), Ok(1.0));
+        assert_eq!(eval("2x-3", &[]), Ok(-6.0));
+        assert_eq!(eval("+.5", &[]), Ok(0.5));
+    }
+
+    #[test]
+    fn variables() {
+        assert_eq!(eval("$12", &[(12, 4.0), (1, 1.0)]), Ok(4.0));
+        assert_eq!(eval("$3", &[]), Ok(0.0));
+        assert!(eval("$", &[]).is_err());
+    }
+
+    #[test]
+    fn malformed_expressions() {
+        assert!(eval("(1+2", &[]).is_err());
+        assert!(eval("1+", &[]).is_err());
+        assert!(eval("1)", &[]).is_err());
+    }
+}
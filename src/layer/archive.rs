@@ -0,0 +1,76 @@
+use std::{
+    fs::File,
+    io::{BufReader, Cursor, Read},
+    path::{Path, PathBuf},
+};
+
+use zip::ZipArchive;
+
+use crate::ui::message::CanvasLayer;
+
+use super::{classify::classify_file, error::LayerError};
+
+/// Content of a fabrication file. An entry of a zip archive is named like a file in a
+/// folder, `board.zip/board-F_Cu.gbr`, and read in memory without being extracted.
+pub fn open_source(path: &Path) -> Result<BufReader<Box<dyn Read + Send>>, LayerError> {
+    if path.is_file() {
+        let file = File::open(path).map_err(|e| LayerError::Io(e.to_string()))?;
+        return Ok(BufReader::new(Box::new(file)));
+    }
+
+    let (archive_path, entry) = path
+        .ancestors()
+        .skip(1)
+        .find(|ancestor| is_archive(ancestor) && ancestor.is_file())
+        .and_then(|archive| Some((archive, path.strip_prefix(archive).ok()?)))
+        .ok_or_else(|| LayerError::Io(format!("{} not found", path.display())))?;
+
+    let mut archive = open_archive(archive_path)?;
+    let entry_name = entry
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+    let mut entry = archive
+        .by_name(&entry_name)
+        .map_err(|e| LayerError::Io(format!("{entry_name}: {e}")))?;
+
+    let mut content = Vec::new();
+    entry
+        .read_to_end(&mut content)
+        .map_err(|e| LayerError::Io(e.to_string()))?;
+
+    Ok(BufReader::new(Box::new(Cursor::new(content))))
+}
+
+pub fn is_archive(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("zip"))
+}
+
+/// Files of a zip archive, each with the layer it seems to belong to.
+pub fn classify_archive(
+    archive_path: &Path,
+) -> Result<Vec<(PathBuf, Option<CanvasLayer>)>, LayerError> {
+    let archive = open_archive(archive_path)?;
+
+    let mut entries: Vec<PathBuf> = archive
+        .file_names()
+        .filter(|name| !name.ends_with('/'))
+        .map(|name| archive_path.join(name))
+        .collect();
+    entries.sort();
+
+    Ok(entries
+        .into_iter()
+        .map(|entry| {
+            let canvas_layer = classify_file(&entry);
+            (entry, canvas_layer)
+        })
+        .collect())
+}
+
+fn open_archive(archive_path: &Path) -> Result<ZipArchive<File>, LayerError> {
+    let file = File::open(archive_path).map_err(|e| LayerError::Io(e.to_string()))?;
+    ZipArchive::new(file).map_err(|e| LayerError::Io(format!("{}: {e}", archive_path.display())))
+}
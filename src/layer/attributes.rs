@@ -0,0 +1,126 @@
+use clipper2::{Paths, PointInPolygonResult};
+use gerber_types::{
+    ApertureAttribute, ApertureFunction, AttributeDeletionCriterion, FileAttribute, FileFunction,
+    FilePolarity, Net, ObjectAttribute,
+};
+
+use super::vec2::Vec2;
+
+/// X2 attributes (%TF) describing the whole file.
+#[derive(Debug, Default, Clone)]
+pub struct FileAttributes {
+    pub function: Option<FileFunction>,
+    pub polarity: Option<FilePolarity>,
+}
+
+impl FileAttributes {
+    pub fn add(&mut self, attribute: &FileAttribute) {
+        match attribute {
+            FileAttribute::FileFunction(function) => self.function = Some(function.clone()),
+            FileAttribute::FilePolarity(polarity) => self.polarity = Some(*polarity),
+            _ => (),
+        }
+    }
+}
+
+/// X2 attributes attached to one object, from its aperture (%TA) and the object
+/// dictionary (%TO) when it was drawn.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ObjectAttributes {
+    pub aperture_function: Option<ApertureFunction>,
+    pub net: Option<String>,
+    pub component: Option<String>,
+    pub pin: Option<String>,
+}
+
+impl ObjectAttributes {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn set_aperture(&mut self, attribute: &ApertureAttribute) {
+        if let ApertureAttribute::ApertureFunction(function) = attribute {
+            self.aperture_function = Some(function.clone());
+        }
+    }
+
+    pub fn set_object(&mut self, attribute: &ObjectAttribute) {
+        match attribute {
+            ObjectAttribute::Net(Net::None) => self.net = None,
+            ObjectAttribute::Net(Net::NotConnected) => self.net = Some("N/C".to_string()),
+            ObjectAttribute::Net(Net::Connected(names)) => self.net = Some(names.join(", ")),
+            ObjectAttribute::Component(component) => self.component = Some(component.clone()),
+            ObjectAttribute::Pin(pin) => self.pin = Some(format!("{}-{}", pin.refdes, pin.name)),
+            _ => (),
+        }
+    }
+
+    /// Forget attributes deleted by %TD, `aperture` holding the aperture dictionary.
+    pub fn delete(criterion: &AttributeDeletionCriterion, aperture: &mut Self, object: &mut Self) {
+        let name = match criterion {
+            AttributeDeletionCriterion::AllApertureAndObjectAttributes => {
+                *aperture = Self::default();
+                *object = Self::default();
+                return;
+            }
+            AttributeDeletionCriterion::SingleObjectAttribute(name)
+            | AttributeDeletionCriterion::SingleApertureAttribute(name) => name,
+        };
+
+        match name.trim_start_matches('.') {
+            "AperFunction" => aperture.aperture_function = None,
+            "N" => object.net = None,
+            "C" => object.component = None,
+            "P" => object.pin = None,
+            _ => (),
+        }
+    }
+
+    /// Aperture attributes completed by the object ones.
+    pub fn merged(&self, object: &Self) -> Self {
+        Self {
+            aperture_function: self.aperture_function.clone(),
+            ..object.clone()
+        }
+    }
+
+    /// One line summary, e.g. "Net GND, U1-3, SmdPad(...)".
+    pub fn description(&self) -> String {
+        let mut parts: Vec<String> = Vec::new();
+
+        if let Some(net) = &self.net {
+            parts.push(format!("Net {net}"));
+        }
+
+        match (&self.pin, &self.component) {
+            (Some(pin), _) => parts.push(pin.clone()),
+            (None, Some(component)) => parts.push(component.clone()),
+            (None, None) => (),
+        }
+
+        if let Some(function) = &self.aperture_function {
+            parts.push(format!("{function:?}"));
+        }
+
+        parts.join(", ")
+    }
+}
+
+/// Drawn object keeping its attributes, to find what is under a point.
+#[derive(Debug, Clone)]
+pub struct LayerObject {
+    pub paths: Paths,
+    pub attributes: ObjectAttributes,
+}
+
+impl LayerObject {
+    /// Inside when within an odd number of contours, holes included.
+    pub fn contains(&self, point: Vec2) -> bool {
+        self.paths
+            .iter()
+            .filter(|path| path.is_point_inside(point.into()) != PointInPolygonResult::IsOutside)
+            .count()
+            % 2
+            == 1
+    }
+}
@@ -0,0 +1,92 @@
+use std::{io::BufRead, path::Path};
+
+use crate::ui::message::CanvasLayer;
+
+use super::archive::open_source;
+
+/// Lines read at the top of a file to find its X2 file function.
+const HEADER_LINES: usize = 50;
+
+/// Layer a fabrication file belongs to, from its X2 file function when it has one, or
+/// from the naming conventions of KiCad, Eagle, Altium/Protel and EasyEDA. A file
+/// function without layer, like a drill map, is trusted over the file name.
+pub fn classify_file(path: &Path) -> Option<CanvasLayer> {
+    match file_function(path) {
+        Some(function) => function_layer(&function),
+        None => name_layer(path),
+    }
+}
+
+fn file_function(path: &Path) -> Option<String> {
+    let reader = open_source(path).ok()?;
+
+    // Gerber attribute, or the Excellon comment written by KiCad
+    reader
+        .lines()
+        .take(HEADER_LINES)
+        .map_while(Result::ok)
+        .find_map(|line| {
+            line.split_once("TF.FileFunction,")
+                .map(|(_, function)| function.trim_end_matches(['*', '%']).to_string())
+        })
+}
+
+/// Layer of an X2 file function value, e.g. "Copper,L1,Top".
+pub fn function_layer(function: &str) -> Option<CanvasLayer> {
+    let fields: Vec<&str> = function.split(',').collect();
+
+    match fields.first()? {
+        &"Copper" if fields.contains(&"Top") => Some(CanvasLayer::Top),
+        &"Copper" if fields.contains(&"Bot") => Some(CanvasLayer::Bottom),
+        &"Profile" => Some(CanvasLayer::Outline),
+        &"Plated" | &"NonPlated" => Some(CanvasLayer::Drill),
+        _ => None,
+    }
+}
+
+fn name_layer(path: &Path) -> Option<CanvasLayer> {
+    let name = path.file_stem()?.to_str()?.to_lowercase();
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or("")
+        .to_lowercase();
+
+    let name_has = |parts: &[&str]| parts.iter().any(|part| name.contains(part));
+
+    match extension.as_str() {
+        // Altium/Protel, EasyEDA
+        "gtl" => Some(CanvasLayer::Top),
+        "gbl" => Some(CanvasLayer::Bottom),
+        "gko" | "gm1" | "gml" => Some(CanvasLayer::Outline),
+        // Eagle
+        "cmp" => Some(CanvasLayer::Top),
+        "sol" => Some(CanvasLayer::Bottom),
+        "dim" | "mil" => Some(CanvasLayer::Outline),
+        "drl" | "drd" | "xln" | "exc" => Some(CanvasLayer::Drill),
+        // KiCad, Eagle CAM and others name the layer in a generic extension
+        "gbr" | "ger" | "pho" | "art" | "txt" => {
+            // Drill maps, e.g. KiCad "board-PTH-drl_map.gbr", are drawings of the holes
+            if name_has(&["_map"]) {
+                None
+            } else if name_has(&["-pth", "-npth", "drl", "drill"]) {
+                Some(CanvasLayer::Drill)
+            } else if name_has(&["f_cu", "f.cu", "copper_top", "toplayer", "top_copper"]) {
+                Some(CanvasLayer::Top)
+            } else if name_has(&[
+                "b_cu",
+                "b.cu",
+                "copper_bottom",
+                "bottomlayer",
+                "bottom_copper",
+            ]) {
+                Some(CanvasLayer::Bottom)
+            } else if name_has(&["edge_cuts", "edge.cuts", "profile", "outline"]) {
+                Some(CanvasLayer::Outline)
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
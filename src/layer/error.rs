@@ -0,0 +1,66 @@
+use std::fmt::Display;
+
+/// Why a file could not be turned into a `Layer`. Command indexes start at 0, in the
+/// order of the parsed document; source lines start at 1.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LayerError {
+    Io(String),
+    Parse {
+        line: Option<(usize, String)>,
+        message: String,
+    },
+    UnsupportedCommand {
+        index: usize,
+        command: String,
+    },
+    MissingAperture {
+        index: usize,
+        code: Option<i32>,
+    },
+    EmptyOutline,
+    NoCopper,
+    OpenOutline {
+        x: f64,
+        y: f64,
+    },
+    OutlineIntersection {
+        x: f64,
+        y: f64,
+    },
+}
+
+impl Display for LayerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LayerError::Io(message) => write!(f, "Cannot read file: {message}"),
+            LayerError::Parse {
+                line: Some((number, content)),
+                message,
+            } => write!(f, "Parse error line {number} '{content}': {message}"),
+            LayerError::Parse {
+                line: None,
+                message,
+            } => write!(f, "Parse error: {message}"),
+            LayerError::UnsupportedCommand { index, command } => {
+                write!(f, "Unsupported command #{index}: {command}")
+            }
+            LayerError::MissingAperture {
+                index,
+                code: Some(code),
+            } => write!(f, "Command #{index} selects undefined aperture D{code}"),
+            LayerError::MissingAperture { index, code: None } => {
+                write!(f, "Command #{index} draws without a selected aperture")
+            }
+            LayerError::EmptyOutline => write!(f, "The outline file draws no board edge"),
+            LayerError::NoCopper => write!(f, "No copper layer to generate the outline around"),
+            LayerError::OpenOutline { x, y } => {
+                write!(f, "Board outline is open at ({x:.3}, {y:.3})")
+            }
+            LayerError::OutlineIntersection { x, y } => {
+                write!(f, "Board outline crosses itself at ({x:.3}, {y:.3})")
+            }
+        }
+    }
+}
+
+impl std::error::Error for LayerError {}
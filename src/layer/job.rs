@@ -0,0 +1,80 @@
+use std::{
+    fs,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use log::warn;
+use serde_json::Value;
+
+use crate::ui::message::CanvasLayer;
+
+use super::{
+    archive::open_source,
+    classify::{classify_file, function_layer},
+    error::LayerError,
+};
+
+/// What a Gerber job file (.gbrjob) tells about the board and its fabrication files.
+#[derive(Debug, Default, Clone)]
+pub struct GerberJob {
+    /// In millimetres
+    pub board_thickness: Option<f64>,
+    pub copper_layers: Option<u64>,
+    /// Files listed by the job, next to it, with the layer of their file function
+    pub files: Vec<(PathBuf, CanvasLayer)>,
+}
+
+/// Read a job file, as written by KiCad or Altium. Files with a function that is not
+/// a layer of this application (solder mask, silkscreen...) are left out.
+pub fn read_job(path: &Path) -> Result<GerberJob, LayerError> {
+    let mut content = String::new();
+    open_source(path)?
+        .read_to_string(&mut content)
+        .map_err(|e| LayerError::Io(e.to_string()))?;
+
+    let json: Value = serde_json::from_str(&content).map_err(|e| LayerError::Parse {
+        line: Some((e.line(), String::new())),
+        message: e.to_string(),
+    })?;
+
+    let folder = path.parent().unwrap_or(Path::new(""));
+    let specs = &json["GeneralSpecs"];
+
+    let mut files: Vec<(PathBuf, CanvasLayer)> = match json["FilesAttributes"].as_array() {
+        Some(files) => files
+            .iter()
+            .filter_map(|file| {
+                let name = file["Path"].as_str()?;
+                let function = file["FileFunction"].as_str()?;
+                let canvas_layer = function_layer(function)?;
+                Some((folder.join(name), canvas_layer))
+            })
+            .collect(),
+        None => {
+            warn!("Job file lists no files");
+            Vec::new()
+        }
+    };
+
+    // KiCad does not list drill files, they are looked for next to the job
+    if !files
+        .iter()
+        .any(|(_, canvas_layer)| *canvas_layer == CanvasLayer::Drill)
+    {
+        if let Ok(entries) = fs::read_dir(folder) {
+            let mut drills: Vec<PathBuf> = entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|file| classify_file(file) == Some(CanvasLayer::Drill))
+                .collect();
+            drills.sort();
+            files.extend(drills.into_iter().map(|file| (file, CanvasLayer::Drill)));
+        }
+    }
+
+    Ok(GerberJob {
+        board_thickness: specs["BoardThickness"].as_f64(),
+        copper_layers: specs["LayerNumber"].as_u64(),
+        files,
+    })
+}
@@ -1,119 +1,636 @@
 use std::collections::HashMap;
+use std::f64::consts::{FRAC_PI_2, PI, TAU};
 
 use clipper2::{FillRule, Path, Paths, Point};
-use gerber_parser::gerber_doc::GerberDoc;
-use gerber_types::{Aperture, InterpolationMode};
+use gerber_parser::{ContentError, GerberDoc, GerberParserErrorWithContext};
+use gerber_types::{
+    Aperture, ApertureBlock, ApertureMacro, AxisSelect, Command, CoordinateFormat, CoordinateMode,
+    ExtendedCode, FunctionCode, GCode, ImageMirroring, ImageOffset, ImagePolarity, ImageRotation,
+    ImageScaling, InterpolationMode, MacroDecimal, Mirroring, Polarity, QuadrantMode,
+    StepAndRepeat, Unit,
+};
 use iced::widget::canvas::Frame;
 use iced::widget::canvas::Path as IcedPath;
 use iced::Color;
 use iced::Point as IcedPoint;
-use log::error;
+use log::{error, warn};
 
+use super::aperture_macro;
+use super::attributes::{FileAttributes, LayerObject, ObjectAttributes};
+use super::error::LayerError;
+use super::outline::{board_outline, generated_outline, GeneratedOutline};
 use super::vec2::Vec2;
+use crate::drill::drill_file::DrillFile;
 
-const CICRLE_RES: u32 = 100;
+pub const DEFAULT_ARC_TOLERANCE: f64 = 0.01;
 
+pub const DEFAULT_OUTLINE_GAP: f64 = 0.05;
+
+pub(crate) const MM_PER_INCH: f64 = 25.4;
+
+const MIN_CIRCLE_RES: u32 = 8;
+
+#[derive(Debug, Clone, Copy)]
+pub struct LayerOptions {
+    /// Maximum distance between an arc (or circle) and the chords approximating it.
+    pub arc_tolerance: f64,
+    /// Largest gap between the ends of two board outline edges still joining them.
+    pub outline_gap: f64,
+}
+
+impl Default for LayerOptions {
+    fn default() -> Self {
+        Self {
+            arc_tolerance: DEFAULT_ARC_TOLERANCE,
+            outline_gap: DEFAULT_OUTLINE_GAP,
+        }
+    }
+}
+
+/// Object drawn while a block aperture (%AB) or a step and repeat (%SR) is open, with the
+/// attributes it was drawn with.
+type CapturedObject = (Paths, Polarity, ObjectAttributes);
+
+/// Objects drawn while a block aperture (%AB) or a step and repeat (%SR) is open, kept
+/// aside to be replicated once it is closed.
+struct Capture {
+    kind: CaptureKind,
+    objects: Vec<CapturedObject>,
+}
+
+enum CaptureKind {
+    Block(i32),
+    StepAndRepeat {
+        repeat_x: u32,
+        repeat_y: u32,
+        step: Vec2,
+    },
+}
+
+/// Aperture transformation set by %LM, %LR and %LS. Mirroring, then rotation, then
+/// scaling are applied around the aperture origin.
+#[derive(Debug, Clone, Copy)]
+struct ApertureTransform {
+    mirroring: Mirroring,
+    rotation: f64,
+    scaling: f64,
+}
+
+impl Default for ApertureTransform {
+    fn default() -> Self {
+        Self {
+            mirroring: Mirroring::None,
+            rotation: 0.0,
+            scaling: 1.0,
+        }
+    }
+}
+
+impl ApertureTransform {
+    /// Transform an aperture shape drawn at `origin`.
+    fn apply(&self, paths: Paths, origin: Vec2) -> Paths {
+        if self.mirroring == Mirroring::None && self.rotation == 0.0 && self.scaling == 1.0 {
+            return paths;
+        }
+
+        let (mirror_x, mirror_y) = match self.mirroring {
+            Mirroring::None => (1.0, 1.0),
+            Mirroring::X => (-1.0, 1.0),
+            Mirroring::Y => (1.0, -1.0),
+            Mirroring::XY => (-1.0, -1.0),
+        };
+        let radians = self.rotation.to_radians();
+
+        Paths::new(
+            paths
+                .iter()
+                .map(|path| {
+                    let mut points: Vec<Point> = path
+                        .iter()
+                        .map(|p| {
+                            let mut v = Vec2::new(
+                                (p.x() - origin.x) * mirror_x,
+                                (p.y() - origin.y) * mirror_y,
+                            )
+                            .rotated(radians);
+                            v.mult(self.scaling);
+                            (v + origin).into()
+                        })
+                        .collect();
+
+                    // Keep the orientation, holes and outlines would swap otherwise
+                    if mirror_x * mirror_y < 0.0 {
+                        points.reverse();
+                    }
+                    Path::new(points)
+                })
+                .collect(),
+        )
+    }
+}
+
+/// Geometry of a layer, always in millimetres whatever the unit of its source file.
 #[derive(Debug, Default, Clone)]
 pub struct Layer {
     paths: Paths,
     color: Color,
+    unit: Option<Unit>,
+    format: Option<CoordinateFormat>,
+    attributes: FileAttributes,
+    objects: Vec<LayerObject>,
 }
 
 impl Layer {
-    pub fn from_gerber(gerber: &GerberDoc, color: Color) -> Self {
+    pub fn from_gerber(
+        gerber: &GerberDoc,
+        color: Color,
+        options: LayerOptions,
+    ) -> Result<Self, LayerError> {
         let mut geos = Self::default();
         let apertures: &HashMap<i32, Aperture> = &gerber.apertures;
 
+        let macros: HashMap<&str, &ApertureMacro> = gerber
+            .commands()
+            .into_iter()
+            .filter_map(|cmd| match cmd {
+                Command::ExtendedCode(ExtendedCode::ApertureMacro(am)) => {
+                    Some((am.name.as_str(), am))
+                }
+                _ => None,
+            })
+            .collect();
+
+        geos.unit = Self::gerber_unit(gerber);
+        geos.format = gerber.format_specification;
+
+        let scale = Self::gerber_scale(gerber);
+        let mut incremental = Self::gerber_incremental(gerber);
+
         let mut current_pos = Vec2::default();
-        let mut current_aperture: Option<&Aperture> = None;
+        let mut current_aperture: Option<i32> = None;
         let mut current_mode: InterpolationMode = InterpolationMode::Linear;
+        let mut current_quadrant: QuadrantMode = QuadrantMode::Multi;
+        let mut current_polarity: Polarity = Polarity::Dark;
+        let mut region_mode = false;
+        let mut region_contour: Vec<Vec2> = Vec::new();
+        let mut captures: Vec<Capture> = Vec::new();
+        let mut transform = ApertureTransform::default();
+        let mut blocks: HashMap<i32, Vec<CapturedObject>> = HashMap::new();
+        let mut aperture_dictionary = ObjectAttributes::default();
+        let mut object_dictionary = ObjectAttributes::default();
+        let mut aperture_attributes: HashMap<i32, ObjectAttributes> = HashMap::new();
 
         geos.color = color;
 
-        for cmd in &gerber.commands {
+        for (index, cmd) in gerber.commands.iter().enumerate() {
+            let Some(cmd) = Self::command(cmd)? else {
+                continue;
+            };
+
             match cmd {
                 gerber_types::Command::FunctionCode(function_code) => match function_code {
                     gerber_types::FunctionCode::DCode(dcode) => match dcode {
                         gerber_types::DCode::SelectAperture(id) => {
-                            if apertures.contains_key(id) {
-                                current_aperture = Some(&apertures[id]);
+                            if apertures.contains_key(id) || blocks.contains_key(id) {
+                                current_aperture = Some(*id);
                             } else {
-                                error!("Unknown aperture #{id}");
-                                current_aperture = None;
+                                return Err(LayerError::MissingAperture {
+                                    index,
+                                    code: Some(*id),
+                                });
                             }
                         }
                         gerber_types::DCode::Operation(operation) => match operation {
-                            gerber_types::Operation::Move(coord) => current_pos.set(coord),
+                            gerber_types::Operation::Move(coord) => {
+                                if region_mode {
+                                    geos.fill_contour(
+                                        &mut captures,
+                                        &mut region_contour,
+                                        current_polarity,
+                                        &aperture_dictionary.merged(&object_dictionary),
+                                    );
+                                }
+                                current_pos.set(coord, scale, incremental);
+                            }
                             gerber_types::Operation::Interpolate(
                                 coordinates,
-                                _coordinate_offset,
-                            ) => match current_mode {
-                                InterpolationMode::Linear => {
-                                    let rect: Paths = Self::line_to_rect_path(
-                                        current_pos,
-                                        coordinates.into(),
-                                        Self::thickness_aperture(&current_aperture),
-                                    )
-                                    .into();
-                                    match rect
-                                        .to_clipper_subject()
-                                        .add_clip(Self::aperture_path(
-                                            &current_aperture.unwrap(),
+                                coordinate_offset,
+                            ) => {
+                                let mut target = current_pos;
+                                target.set(coordinates, scale, incremental);
+
+                                let points: Vec<Vec2> = match current_mode {
+                                    InterpolationMode::Linear => vec![current_pos, target],
+                                    InterpolationMode::ClockwiseCircular
+                                    | InterpolationMode::CounterclockwiseCircular => {
+                                        let mut offset: Vec2 = match coordinate_offset {
+                                            Some(offset) => offset.into(),
+                                            None => Vec2::default(),
+                                        };
+                                        offset.mult(scale);
+
+                                        let mut points = vec![current_pos];
+                                        points.extend(Self::arc_points(
                                             current_pos,
-                                        ))
-                                        .add_clip(Self::aperture_path(
-                                            &current_aperture.unwrap(),
-                                            coordinates.into(),
-                                        ))
-                                        .union(FillRule::default())
-                                    {
-                                        Ok(paths) => geos.union(paths),
-                                        Err(e) => {
-                                            error!("Failed to create trace path. Error: {e}")
-                                        }
+                                            target,
+                                            offset,
+                                            current_mode == InterpolationMode::ClockwiseCircular,
+                                            current_quadrant,
+                                            options.arc_tolerance,
+                                        ));
+                                        points
                                     }
-                                    current_pos.set(coordinates);
+                                };
+
+                                if region_mode {
+                                    if region_contour.is_empty() {
+                                        region_contour.push(current_pos);
+                                    }
+                                    region_contour.extend_from_slice(&points[1..]);
+                                } else {
+                                    match current_aperture.map(|id| apertures.get(&id)) {
+                                        Some(Some(aperture)) => {
+                                            match Self::stroke_path(
+                                                aperture, &points, &macros, &transform, scale,
+                                                &options,
+                                            ) {
+                                                Ok(paths) => geos.emit_object(
+                                                    &mut captures,
+                                                    paths,
+                                                    current_polarity,
+                                                    &Self::object_attributes(
+                                                        &aperture_attributes,
+                                                        current_aperture,
+                                                        &object_dictionary,
+                                                    ),
+                                                ),
+                                                Err(e) => {
+                                                    error!(
+                                                        "Failed to create trace path. Error: {e}"
+                                                    )
+                                                }
+                                            }
+                                        }
+                                        Some(None) => {
+                                            error!("Block apertures cannot be used to draw lines")
+                                        }
+                                        None => {
+                                            return Err(LayerError::MissingAperture {
+                                                index,
+                                                code: None,
+                                            })
+                                        }
+                                    };
                                 }
-                                InterpolationMode::ClockwiseCircular => todo!(),
-                                InterpolationMode::CounterclockwiseCircular => todo!(),
-                            },
+                                current_pos = target;
+                            }
                             gerber_types::Operation::Flash(coordinates) => {
+                                if region_mode {
+                                    error!("Flash operation is not allowed in region mode");
+                                }
+
+                                current_pos.set(coordinates, scale, incremental);
+
                                 match current_aperture {
-                                    Some(aperture) => {
-                                        geos.union(
-                                            Self::aperture_path(aperture, coordinates.into())
-                                                .into(),
-                                        );
+                                    Some(id) => match apertures.get(&id) {
+                                        Some(aperture) => geos.emit_object(
+                                            &mut captures,
+                                            transform.apply(
+                                                Self::flash_path(
+                                                    aperture,
+                                                    current_pos,
+                                                    &macros,
+                                                    scale,
+                                                    &options,
+                                                ),
+                                                current_pos,
+                                            ),
+                                            current_polarity,
+                                            &Self::object_attributes(
+                                                &aperture_attributes,
+                                                current_aperture,
+                                                &object_dictionary,
+                                            ),
+                                        ),
+                                        None => {
+                                            // Objects of the block are drawn relative to the
+                                            // flash, their polarity toggled by a clear flash
+                                            for (paths, polarity, attributes) in &blocks[&id] {
+                                                let polarity = match current_polarity {
+                                                    Polarity::Dark => *polarity,
+                                                    Polarity::Clear => match polarity {
+                                                        Polarity::Dark => Polarity::Clear,
+                                                        Polarity::Clear => Polarity::Dark,
+                                                    },
+                                                };
+                                                geos.emit_object(
+                                                    &mut captures,
+                                                    transform
+                                                        .apply(paths.clone(), Vec2::default())
+                                                        .translate(current_pos.x, current_pos.y),
+                                                    polarity,
+                                                    attributes,
+                                                );
+                                            }
+                                        }
+                                    },
+                                    None => {
+                                        return Err(LayerError::MissingAperture {
+                                            index,
+                                            code: None,
+                                        })
                                     }
-                                    None => error!("No aperture selected for flash operation"),
                                 };
-                                current_pos.set(coordinates);
                             }
                         },
                     },
                     gerber_types::FunctionCode::GCode(gcode) => match gcode {
-                        gerber_types::GCode::InterpolationMode(mode) => current_mode = mode.clone(),
-                        gerber_types::GCode::RegionMode(_) => {
-                            error!("Region mode command not supported...")
+                        gerber_types::GCode::InterpolationMode(mode) => current_mode = *mode,
+                        gerber_types::GCode::RegionMode(enabled) => {
+                            if region_mode {
+                                geos.fill_contour(
+                                    &mut captures,
+                                    &mut region_contour,
+                                    current_polarity,
+                                    &aperture_dictionary.merged(&object_dictionary),
+                                );
+                            }
+                            region_mode = *enabled;
                         }
-                        gerber_types::GCode::QuadrantMode(_) => {
-                            error!("Quadrant Mode command not supported...")
+                        gerber_types::GCode::QuadrantMode(mode) => current_quadrant = *mode,
+                        gerber_types::GCode::Comment(_) | gerber_types::GCode::SelectAperture => (),
+                        gerber_types::GCode::Unit(_) => (),
+                        gerber_types::GCode::CoordinateMode(mode) => {
+                            incremental = *mode == CoordinateMode::Incremental
                         }
-                        gerber_types::GCode::Comment(_) => (),
                     },
                     gerber_types::FunctionCode::MCode(mcode) => match mcode {
                         gerber_types::MCode::EndOfFile => (),
                     },
                 },
-                gerber_types::Command::ExtendedCode(_extended_code) => {
-                    error!("No exented code supported...");
-                }
+                gerber_types::Command::ExtendedCode(extended_code) => match extended_code {
+                    ExtendedCode::LoadPolarity(polarity) => {
+                        if region_mode {
+                            error!("Load polarity command is not allowed in region mode");
+                        }
+                        current_polarity = *polarity;
+                    }
+                    ExtendedCode::LoadMirroring(mirroring) => transform.mirroring = *mirroring,
+                    ExtendedCode::LoadRotation(rotation) => transform.rotation = rotation.rotation,
+                    ExtendedCode::LoadScaling(scaling) => transform.scaling = scaling.scale,
+                    ExtendedCode::ApertureBlock(ApertureBlock::Open { code }) => {
+                        captures.push(Capture {
+                            kind: CaptureKind::Block(*code),
+                            objects: Vec::new(),
+                        });
+                    }
+                    ExtendedCode::ApertureBlock(ApertureBlock::Close) => match captures.pop() {
+                        Some(Capture {
+                            kind: CaptureKind::Block(code),
+                            objects,
+                        }) => {
+                            blocks.insert(code, objects);
+                        }
+                        Some(capture) => {
+                            error!("Block aperture closed inside a step and repeat");
+                            geos.close_capture(&mut captures, capture);
+                        }
+                        None => error!("Block aperture closed but none is open"),
+                    },
+                    ExtendedCode::StepAndRepeat(StepAndRepeat::Open {
+                        repeat_x,
+                        repeat_y,
+                        distance_x,
+                        distance_y,
+                    }) => {
+                        // A new step and repeat implicitly closes the previous one
+                        if let Some(Capture {
+                            kind: CaptureKind::StepAndRepeat { .. },
+                            ..
+                        }) = captures.last()
+                        {
+                            let capture = captures.pop().unwrap();
+                            geos.close_capture(&mut captures, capture);
+                        }
+
+                        captures.push(Capture {
+                            kind: CaptureKind::StepAndRepeat {
+                                repeat_x: *repeat_x,
+                                repeat_y: *repeat_y,
+                                step: Vec2::new(distance_x * scale, distance_y * scale),
+                            },
+                            objects: Vec::new(),
+                        });
+                    }
+                    ExtendedCode::StepAndRepeat(StepAndRepeat::Close) => match captures.pop() {
+                        Some(capture) => geos.close_capture(&mut captures, capture),
+                        None => error!("Step and repeat closed but none is open"),
+                    },
+                    // Apertures get the attributes defined before them
+                    ExtendedCode::ApertureDefinition(definition) => {
+                        if !aperture_dictionary.is_empty() {
+                            aperture_attributes
+                                .insert(definition.code, aperture_dictionary.clone());
+                        }
+                    }
+                    ExtendedCode::FileAttribute(attribute) => geos.attributes.add(attribute),
+                    ExtendedCode::ApertureAttribute(attribute) => {
+                        aperture_dictionary.set_aperture(attribute)
+                    }
+                    ExtendedCode::ObjectAttribute(attribute) => {
+                        object_dictionary.set_object(attribute)
+                    }
+                    ExtendedCode::DeleteAttribute(criterion) => ObjectAttributes::delete(
+                        criterion,
+                        &mut aperture_dictionary,
+                        &mut object_dictionary,
+                    ),
+                    // Read from the document before drawing
+                    ExtendedCode::CoordinateFormat(_)
+                    | ExtendedCode::Unit(_)
+                    | ExtendedCode::ApertureMacro(_) => (),
+                    ExtendedCode::ImageName(_)
+                    | ExtendedCode::ImagePolarity(ImagePolarity::Positive) => (),
+                    // Deprecated image transformations, still written by some CAD
+                    // tools with values changing nothing
+                    ExtendedCode::OffsetImage(ImageOffset { a: 0.0, b: 0.0 })
+                    | ExtendedCode::ScaleImage(ImageScaling { a: 1.0, b: 1.0 })
+                    | ExtendedCode::MirrorImage(ImageMirroring::None)
+                    | ExtendedCode::RotateImage(ImageRotation::None)
+                    | ExtendedCode::AxisSelect(AxisSelect::AXBY) => {
+                        warn!("Ignoring deprecated command {extended_code:?}")
+                    }
+                    _ => {
+                        return Err(LayerError::UnsupportedCommand {
+                            index,
+                            command: format!("{extended_code:?}"),
+                        })
+                    }
+                },
+            }
+        }
+
+        if region_mode {
+            warn!("Region not closed at the end of the file");
+            geos.fill_contour(
+                &mut captures,
+                &mut region_contour,
+                current_polarity,
+                &aperture_dictionary.merged(&object_dictionary),
+            );
+        }
+
+        while let Some(capture) = captures.pop() {
+            warn!("Block aperture or step and repeat not closed at the end of the file");
+            geos.close_capture(&mut captures, capture);
+        }
+
+        Ok(geos)
+    }
+
+    /// Unit of a Gerber file. Deprecated G70/G71 codes are only used when the file has no
+    /// %MO command.
+    pub fn gerber_unit(gerber: &GerberDoc) -> Option<Unit> {
+        gerber.units.or_else(|| {
+            gerber.commands().into_iter().find_map(|cmd| match cmd {
+                Command::FunctionCode(FunctionCode::GCode(GCode::Unit(unit))) => Some(*unit),
+                _ => None,
+            })
+        })
+    }
+
+    /// Millimetres per unit of a Gerber file coordinates.
+    pub(crate) fn gerber_scale(gerber: &GerberDoc) -> f64 {
+        match Self::gerber_unit(gerber) {
+            Some(Unit::Millimeters) => 1.0,
+            Some(Unit::Inches) => MM_PER_INCH,
+            None => {
+                warn!("No unit specified, assuming millimetres");
+                1.0
             }
         }
+    }
+
+    /// Whether the coordinates of a Gerber file start incremental, before any G90/G91.
+    pub(crate) fn gerber_incremental(gerber: &GerberDoc) -> bool {
+        matches!(
+            gerber.format_specification,
+            Some(CoordinateFormat {
+                coordinate_mode: CoordinateMode::Incremental,
+                ..
+            })
+        )
+    }
+
+    /// A parsed Gerber command, none for a missing end of file: the drawing is complete
+    /// anyway.
+    pub(crate) fn command(
+        cmd: &Result<Command, GerberParserErrorWithContext>,
+    ) -> Result<Option<&Command>, LayerError> {
+        match cmd {
+            Ok(cmd) => Ok(Some(cmd)),
+            Err(e) if matches!(e.error, ContentError::NoEndOfFile) => {
+                warn!("{}", e.error);
+                Ok(None)
+            }
+            Err(e) => Err(LayerError::Parse {
+                line: e.line.clone(),
+                message: e.error.to_string(),
+            }),
+        }
+    }
 
+    /// One circle per hole, the size of its tool.
+    pub fn from_drill(drill: &DrillFile, color: Color, options: LayerOptions) -> Self {
+        let mut geos = Self {
+            color,
+            unit: drill.unit,
+            ..Default::default()
+        };
+
+        let holes: Vec<Path> = drill
+            .tools
+            .iter()
+            .flat_map(|tool| {
+                let radius = tool.diameter / 2.0;
+                let resolution = Self::circle_resolution(radius, options.arc_tolerance);
+                tool.holes
+                    .iter()
+                    .map(move |hole| Self::create_circle_path(hole.position, radius, resolution))
+            })
+            .collect();
+        let slots: Vec<Path> = drill
+            .tools
+            .iter()
+            .flat_map(|tool| {
+                tool.slots.iter().map(|slot| {
+                    Self::create_stadium_path(
+                        slot.start,
+                        slot.end,
+                        slot.width,
+                        options.arc_tolerance,
+                    )
+                })
+            })
+            .collect();
+
+        // All counterclockwise: routed segments overlapping at their joints, or a slot
+        // crossing a hole, must not cancel each other out
+        match Paths::new(holes)
+            .to_clipper_subject()
+            .add_clip(Paths::new(slots))
+            .union(FillRule::NonZero)
+        {
+            Ok(paths) => geos.paths = paths,
+            Err(e) => error!("Failed to union the holes. Error: {e}"),
+        }
         geos
     }
 
+    /// The board shape drawn by an outline file, instead of the copper of its lines.
+    pub fn from_outline(
+        gerber: &GerberDoc,
+        color: Color,
+        options: LayerOptions,
+    ) -> Result<Self, LayerError> {
+        let mut geos = Self {
+            color,
+            unit: Self::gerber_unit(gerber),
+            format: gerber.format_specification,
+            ..Default::default()
+        };
+
+        for cmd in gerber.commands() {
+            if let Command::ExtendedCode(ExtendedCode::FileAttribute(attribute)) = cmd {
+                geos.attributes.add(attribute);
+            }
+        }
+
+        geos.union(board_outline(gerber, &options)?);
+        Ok(geos)
+    }
+
+    /// A board outline around the copper of `layers`, for projects without outline file.
+    pub fn from_copper(
+        layers: &[&Layer],
+        color: Color,
+        outline: &GeneratedOutline,
+    ) -> Result<Self, LayerError> {
+        let copper = Paths::new(
+            layers
+                .iter()
+                .flat_map(|layer| layer.paths.iter().cloned())
+                .collect(),
+        );
+
+        let mut geos = Self {
+            color,
+            ..Default::default()
+        };
+        geos.union(generated_outline(&copper, outline)?);
+        Ok(geos)
+    }
+
     pub fn draw(&self, frame: &mut Frame) {
         for path in self.paths.iter() {
             let mut first_point: Option<IcedPoint> = None;
@@ -143,6 +660,11 @@ impl Layer {
         }
     }
 
+    /// Copper of the layer, merged into non overlapping polygons.
+    pub fn paths(&self) -> &Paths {
+        &self.paths
+    }
+
     pub fn get_bounds(&self) -> iced::Rectangle {
         if self.paths.len() == 0 {
             iced::Rectangle::new((0.0, 0.0).into(), (0.0, 0.0).into())
@@ -162,48 +684,230 @@ impl Layer {
 
     pub fn clear(&mut self) {
         self.paths = Paths::default();
+        self.unit = None;
+        self.format = None;
+        self.attributes = FileAttributes::default();
+        self.objects.clear();
     }
 
-    fn thickness_aperture(aperture: &Option<&Aperture>) -> f64 {
-        match aperture {
-            Some(x) => match x {
-                Aperture::Circle(circle) => circle.diameter,
-                Aperture::Rectangle(rect) => rect.x.max(rect.y),
-                Aperture::Obround(rect) => rect.x.max(rect.y),
-                Aperture::Polygon(_polygon) => todo!(),
-                Aperture::Other(_) => todo!(),
-            },
-            None => {
-                error!("No aperture for thickness");
-                1.0
+    /// X2 attributes of the source file.
+    pub fn attributes(&self) -> &FileAttributes {
+        &self.attributes
+    }
+
+    /// Attributes of the last object drawn under `point`, if it has any.
+    pub fn object_at(&self, point: Vec2) -> Option<&ObjectAttributes> {
+        self.objects
+            .iter()
+            .rev()
+            .find(|object| object.contains(point))
+            .map(|object| &object.attributes)
+    }
+
+    /// Unit of the source file, `None` if it did not specify one.
+    pub fn source_unit(&self) -> Option<Unit> {
+        self.unit
+    }
+
+    pub fn format(&self) -> Option<CoordinateFormat> {
+        self.format
+    }
+
+    /// Unit and coordinate format of the source file, for display.
+    pub fn format_description(&self) -> String {
+        let unit = match self.unit {
+            Some(Unit::Millimeters) => "mm",
+            Some(Unit::Inches) => "inch",
+            None => "unknown unit",
+        };
+
+        match self.format {
+            Some(format) => format!(
+                "{unit}, {}.{} {}",
+                format.integer,
+                format.decimal,
+                match format.coordinate_mode {
+                    CoordinateMode::Absolute => "absolute",
+                    CoordinateMode::Incremental => "incremental",
+                }
+            ),
+            None => format!("{unit}, unknown format"),
+        }
+    }
+
+    /// Stroke a polyline with an aperture: the Minkowski sum of the aperture shape and
+    /// every segment. It is the shape on every vertex plus the area swept by each edge of
+    /// the shape along the segments, all merged together.
+    fn stroke_path(
+        aperture: &Aperture,
+        points: &[Vec2],
+        macros: &HashMap<&str, &ApertureMacro>,
+        transform: &ApertureTransform,
+        scale: f64,
+        options: &LayerOptions,
+    ) -> Result<Paths, clipper2::ClipperError> {
+        let shapes: Vec<Paths> = points
+            .iter()
+            .map(|point| {
+                let shape = Self::aperture_path(aperture, *point, macros, scale, options);
+                transform.apply(shape, *point)
+            })
+            .collect();
+
+        let mut swept: Vec<Path> = Vec::new();
+        for (segment, shape) in points.windows(2).zip(shapes.iter()) {
+            if segment[0].distance(&segment[1]) > 0.0 {
+                let delta = segment[1] - segment[0];
+                for path in shape.iter() {
+                    swept.extend(Self::sweep_edges(path, delta));
+                }
             }
         }
+
+        let ends: Vec<Path> = shapes.into_iter().flatten().collect();
+
+        Paths::new(swept)
+            .to_clipper_subject()
+            .add_clip(Paths::new(ends))
+            .union(FillRule::NonZero)
+    }
+
+    /// Quads swept by every edge of `path` when moved by `delta`, all counterclockwise so
+    /// that overlapping quads never cancel each other out.
+    fn sweep_edges(path: &Path, delta: Vec2) -> Vec<Path> {
+        let vertices: Vec<Vec2> = path.iter().map(|p| Vec2::new(p.x(), p.y())).collect();
+
+        (0..vertices.len())
+            .filter_map(|i| {
+                let a = vertices[i];
+                let b = vertices[(i + 1) % vertices.len()];
+                let edge = b - a;
+
+                let cross = edge.x * delta.y - edge.y * delta.x;
+                if cross == 0.0 {
+                    return None;
+                }
+
+                let quad: Vec<Point> = if cross > 0.0 {
+                    vec![a.into(), b.into(), (b + delta).into(), (a + delta).into()]
+                } else {
+                    vec![a.into(), (a + delta).into(), (b + delta).into(), b.into()]
+                };
+
+                Some(quad.into())
+            })
+            .collect()
+    }
+
+    /// Points of an arc going from `from` to `to`, excluding `from`. `offset` is the I/J
+    /// offset of the center relative to `from`; in single quadrant mode its signs are
+    /// unknown and the center is chosen among the four candidates.
+    pub(super) fn arc_points(
+        from: Vec2,
+        to: Vec2,
+        offset: Vec2,
+        clockwise: bool,
+        quadrant: QuadrantMode,
+        tolerance: f64,
+    ) -> Vec<Vec2> {
+        let (center, sweep) = match quadrant {
+            QuadrantMode::Multi => {
+                let center = from + offset;
+                let mut sweep = Self::arc_sweep(center, from, to, clockwise);
+                if sweep == 0.0 {
+                    sweep = if clockwise { -TAU } else { TAU };
+                }
+                (center, sweep)
+            }
+            QuadrantMode::Single => {
+                let (i, j) = (offset.x.abs(), offset.y.abs());
+                let candidates = [
+                    Vec2::new(from.x + i, from.y + j),
+                    Vec2::new(from.x - i, from.y + j),
+                    Vec2::new(from.x + i, from.y - j),
+                    Vec2::new(from.x - i, from.y - j),
+                ];
+
+                let best = candidates
+                    .iter()
+                    .map(|c| (*c, Self::arc_sweep(*c, from, to, clockwise)))
+                    .filter(|(_, sweep)| sweep.abs() <= FRAC_PI_2 + 1e-6)
+                    .min_by(|(a, _), (b, _)| {
+                        let err_a = (a.distance(&from) - a.distance(&to)).abs();
+                        let err_b = (b.distance(&from) - b.distance(&to)).abs();
+                        err_a.total_cmp(&err_b)
+                    });
+
+                match best {
+                    Some(best) => best,
+                    None => {
+                        error!("No valid center found for single quadrant arc, drawing a line");
+                        return vec![to];
+                    }
+                }
+            }
+        };
+
+        let start_radius = center.distance(&from);
+        let end_radius = center.distance(&to);
+        let start_angle = (from.y - center.y).atan2(from.x - center.x);
+        let segments = (sweep.abs() / Self::chord_angle(start_radius.max(end_radius), tolerance))
+            .ceil()
+            .max(1.0) as u32;
+
+        let mut points: Vec<Vec2> = (1..segments)
+            .map(|k| {
+                let t = k as f64 / segments as f64;
+                let angle = start_angle + sweep * t;
+                let radius = start_radius + (end_radius - start_radius) * t;
+                Vec2::new(
+                    center.x + radius * angle.cos(),
+                    center.y + radius * angle.sin(),
+                )
+            })
+            .collect();
+        points.push(to);
+
+        points
     }
 
-    fn line_to_rect_path(from: Vec2, to: Vec2, thickness: f64) -> Path {
-        let mut vec = Vec2::new_normalize(to.x - from.x, to.y - from.y);
-        vec.mult(thickness / 2.0);
+    /// Signed angle (negative when clockwise) swept from `from` to `to` around `center`.
+    fn arc_sweep(center: Vec2, from: Vec2, to: Vec2, clockwise: bool) -> f64 {
+        let start = (from.y - center.y).atan2(from.x - center.x);
+        let end = (to.y - center.y).atan2(to.x - center.x);
 
-        let norm = Vec2::new(-vec.y, vec.x);
-        let mut inv_norm = norm.clone();
-        inv_norm.inv();
+        if clockwise {
+            -(start - end).rem_euclid(TAU)
+        } else {
+            (end - start).rem_euclid(TAU)
+        }
+    }
 
-        let v: Vec<Point> = vec![
-            (from + norm).into(),
-            (to + norm).into(),
-            (to + inv_norm).into(),
-            (from + inv_norm).into(),
-        ];
+    /// Largest angle a chord can span on a circle of `radius` while staying within `tolerance`.
+    fn chord_angle(radius: f64, tolerance: f64) -> f64 {
+        if radius <= tolerance {
+            PI / 2.0
+        } else {
+            2.0 * (1.0 - tolerance / radius).acos()
+        }
+    }
 
-        v.into()
+    pub(super) fn circle_resolution(radius: f64, tolerance: f64) -> u32 {
+        ((TAU / Self::chord_angle(radius, tolerance)).ceil() as u32).max(MIN_CIRCLE_RES)
     }
 
     fn create_circle_path(center: Vec2, radius: f64, resolution: u32) -> Path {
-        let mut p: Vec<Point> = Vec::with_capacity(resolution as usize);
-        let step = (360.0 / resolution as f64).to_radians();
+        Self::create_polygon_path(center, radius, resolution, 0.0)
+    }
 
-        for i in 0..resolution {
-            let angle = step * (i as f64);
+    /// Regular polygon inscribed in a circle of `radius`, the first vertex at `rotation`
+    /// degrees.
+    fn create_polygon_path(center: Vec2, radius: f64, vertices: u32, rotation: f64) -> Path {
+        let mut p: Vec<Point> = Vec::with_capacity(vertices as usize);
+        let step = (360.0 / vertices as f64).to_radians();
+
+        for i in 0..vertices {
+            let angle = rotation.to_radians() + step * (i as f64);
             p.push(
                 (
                     center.x + radius * angle.cos(),
@@ -216,33 +920,276 @@ impl Layer {
         p.into()
     }
 
-    fn aperture_path(aperture: &Aperture, center: Vec2) -> Path {
+    /// Stadium of `width` by `height`: a rectangle with half circles on its short sides.
+    fn create_obround_path(center: Vec2, width: f64, height: f64, tolerance: f64) -> Path {
+        let half_length = (width - height).abs() / 2.0;
+        let axis = if width > height {
+            Vec2::new(half_length, 0.0)
+        } else {
+            Vec2::new(0.0, half_length)
+        };
+
+        Self::create_stadium_path(center - axis, center + axis, width.min(height), tolerance)
+    }
+
+    /// Rounded ends around `start` and `end`, joined by straight sides.
+    fn create_stadium_path(start: Vec2, end: Vec2, width: f64, tolerance: f64) -> Path {
+        let radius = width / 2.0;
+        let direction = end - start;
+        let start_angle = direction.y.atan2(direction.x) - FRAC_PI_2;
+        // Even so that the tips of the stadium are vertices
+        let segments = Self::circle_resolution(radius, tolerance).div_ceil(4) * 2;
+
+        let mut p: Vec<Point> = Vec::with_capacity(2 * (segments as usize + 1));
+        for (end_center, offset) in [(end, 0.0), (start, PI)] {
+            for i in 0..=segments {
+                let angle = start_angle + offset + PI * i as f64 / segments as f64;
+                p.push(
+                    (
+                        end_center.x + radius * angle.cos(),
+                        end_center.y + radius * angle.sin(),
+                    )
+                        .into(),
+                );
+            }
+        }
+
+        p.into()
+    }
+
+    /// Aperture shape with its optional hole cut out, as drawn by a flash.
+    fn flash_path(
+        aperture: &Aperture,
+        center: Vec2,
+        macros: &HashMap<&str, &ApertureMacro>,
+        scale: f64,
+        options: &LayerOptions,
+    ) -> Paths {
+        let paths = Self::aperture_path(aperture, center, macros, scale, options);
+
+        let hole_diameter = match aperture {
+            Aperture::Circle(circle) => circle.hole_diameter,
+            Aperture::Rectangle(rect) | Aperture::Obround(rect) => rect.hole_diameter,
+            Aperture::Polygon(polygon) => polygon.hole_diameter,
+            Aperture::Macro(_, _) => None,
+        };
+
+        match hole_diameter {
+            Some(diameter) if diameter > 0.0 => {
+                let radius = diameter * scale / 2.0;
+                let hole = Self::create_circle_path(
+                    center,
+                    radius,
+                    Self::circle_resolution(radius, options.arc_tolerance),
+                );
+
+                match paths
+                    .to_clipper_subject()
+                    .add_clip(hole)
+                    .difference(FillRule::NonZero)
+                {
+                    Ok(paths) => paths,
+                    Err(e) => {
+                        error!("Failed to cut aperture hole. Error: {e}");
+                        paths
+                    }
+                }
+            }
+            _ => paths,
+        }
+    }
+
+    /// Aperture shape at `center`, its dimensions given in file units and multiplied by
+    /// `scale`.
+    fn aperture_path(
+        aperture: &Aperture,
+        center: Vec2,
+        macros: &HashMap<&str, &ApertureMacro>,
+        scale: f64,
+        options: &LayerOptions,
+    ) -> Paths {
         match aperture {
             Aperture::Circle(circle) => {
-                Self::create_circle_path(center, circle.diameter / 2.0, CICRLE_RES)
+                let radius = circle.diameter * scale / 2.0;
+                Self::create_circle_path(
+                    center,
+                    radius,
+                    Self::circle_resolution(radius, options.arc_tolerance),
+                )
+                .into()
             }
             Aperture::Rectangle(rect) => {
-                let origin = Vec2::new(center.x - rect.x / 2.0, center.y - rect.y / 2.0);
-                vec![
+                let (width, height) = (rect.x * scale, rect.y * scale);
+                let origin = Vec2::new(center.x - width / 2.0, center.y - height / 2.0);
+                Path::from(vec![
                     (origin.x, origin.y),
-                    (origin.x + rect.x, origin.y),
-                    (origin.x + rect.x, origin.y + rect.y),
-                    (origin.x, origin.y + rect.y),
-                ]
+                    (origin.x + width, origin.y),
+                    (origin.x + width, origin.y + height),
+                    (origin.x, origin.y + height),
+                ])
                 .into()
             }
             Aperture::Obround(rect) => {
                 if rect.x == rect.y {
-                    Self::create_circle_path(center, rect.x / 2.0, CICRLE_RES)
+                    let radius = rect.x * scale / 2.0;
+                    Self::create_circle_path(
+                        center,
+                        radius,
+                        Self::circle_resolution(radius, options.arc_tolerance),
+                    )
+                    .into()
+                } else {
+                    Self::create_obround_path(
+                        center,
+                        rect.x * scale,
+                        rect.y * scale,
+                        options.arc_tolerance,
+                    )
+                    .into()
+                }
+            }
+            Aperture::Polygon(polygon) => {
+                if (3..=12).contains(&polygon.vertices) {
+                    Self::create_polygon_path(
+                        center,
+                        polygon.diameter * scale / 2.0,
+                        polygon.vertices as u32,
+                        polygon.rotation.unwrap_or(0.0),
+                    )
+                    .into()
                 } else {
-                    todo!()
+                    error!(
+                        "Polygon aperture must have between 3 and 12 vertices, found {}",
+                        polygon.vertices
+                    );
+                    Paths::default()
                 }
             }
-            Aperture::Polygon(_polygon) => todo!(),
-            Aperture::Other(_) => {
-                error!("Unsupported \"Other\" aperture type");
-                Path::default()
+            Aperture::Macro(name, args) => {
+                let Some(aperture_macro) = macros.get(name.as_str()) else {
+                    error!("Unknown aperture macro \"{name}\"");
+                    return Paths::default();
+                };
+
+                let args: Vec<f64> = args
+                    .iter()
+                    .flatten()
+                    .map(|arg| match arg {
+                        MacroDecimal::Value(value) => *value,
+                        _ => {
+                            error!("Aperture macro \"{name}\" parameters must be numbers");
+                            0.0
+                        }
+                    })
+                    .collect();
+
+                aperture_macro::macro_paths(
+                    aperture_macro,
+                    &args,
+                    center,
+                    scale,
+                    options.arc_tolerance,
+                )
+            }
+        }
+    }
+
+    /// Fill a region contour (G36/G37) and clear it. The contour is closed if needed,
+    /// and cut-ins become holes thanks to the fill rule.
+    fn fill_contour(
+        &mut self,
+        captures: &mut [Capture],
+        contour: &mut Vec<Vec2>,
+        polarity: Polarity,
+        attributes: &ObjectAttributes,
+    ) {
+        if contour.len() >= 3 {
+            let first = contour[0];
+            let last = contour[contour.len() - 1];
+
+            if first.distance(&last) > f64::EPSILON {
+                warn!("Region contour is not closed, closing it");
+                contour.push(first);
             }
+
+            let path: Vec<Point> = contour.iter().map(|point| (*point).into()).collect();
+            self.emit_object(
+                captures,
+                Paths::new(vec![path.into()]),
+                polarity,
+                attributes,
+            );
+        }
+
+        contour.clear();
+    }
+
+    /// Objects go to the innermost open block or step and repeat, or to the layer itself.
+    /// Dark objects drawn on the layer keep their attributes.
+    fn emit_object(
+        &mut self,
+        captures: &mut [Capture],
+        paths: Paths,
+        polarity: Polarity,
+        attributes: &ObjectAttributes,
+    ) {
+        match captures.last_mut() {
+            Some(capture) => capture.objects.push((paths, polarity, attributes.clone())),
+            None => {
+                if polarity == Polarity::Dark && !attributes.is_empty() {
+                    self.objects.push(LayerObject {
+                        paths: paths.clone(),
+                        attributes: attributes.clone(),
+                    });
+                }
+                self.add_object(paths, polarity)
+            }
+        }
+    }
+
+    fn object_attributes(
+        aperture_attributes: &HashMap<i32, ObjectAttributes>,
+        aperture: Option<i32>,
+        object: &ObjectAttributes,
+    ) -> ObjectAttributes {
+        match aperture.and_then(|id| aperture_attributes.get(&id)) {
+            Some(attributes) => attributes.merged(object),
+            None => object.clone(),
+        }
+    }
+
+    /// Replicate the objects of a step and repeat on its grid. A block closed this way is
+    /// drawn once, where it was defined.
+    fn close_capture(&mut self, captures: &mut [Capture], capture: Capture) {
+        let (repeat_x, repeat_y, step) = match capture.kind {
+            CaptureKind::StepAndRepeat {
+                repeat_x,
+                repeat_y,
+                step,
+            } => (repeat_x, repeat_y, step),
+            CaptureKind::Block(_) => (1, 1, Vec2::default()),
+        };
+
+        for y in 0..repeat_y {
+            for x in 0..repeat_x {
+                for (paths, polarity, attributes) in &capture.objects {
+                    self.emit_object(
+                        captures,
+                        paths.translate(step.x * x as f64, step.y * y as f64),
+                        *polarity,
+                        attributes,
+                    );
+                }
+            }
+        }
+    }
+
+    /// Dark objects are added to the copper, clear objects are removed from everything
+    /// drawn before them.
+    fn add_object(&mut self, paths: Paths, polarity: Polarity) {
+        match polarity {
+            Polarity::Dark => self.union(paths),
+            Polarity::Clear => self.difference(paths),
         }
     }
 
@@ -257,4 +1204,16 @@ impl Layer {
             Err(e) => error!("Failed to union paths. Error: {e}"),
         }
     }
+
+    fn difference(&mut self, paths: Paths) {
+        match self
+            .paths
+            .to_clipper_subject()
+            .add_clip(paths)
+            .difference(FillRule::default())
+        {
+            Ok(new) => self.paths = new,
+            Err(e) => error!("Failed to subtract paths. Error: {e}"),
+        }
+    }
 }
@@ -1,2 +1,9 @@
+pub mod aperture_macro;
+pub mod archive;
+pub mod attributes;
+pub mod classify;
+pub mod error;
+pub mod job;
 pub mod layer;
+pub mod outline;
 pub mod vec2;
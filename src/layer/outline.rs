@@ -0,0 +1,420 @@
+use clipper2::{EndType, FillRule, JoinType, Path, Paths, Point};
+use gerber_parser::GerberDoc;
+use gerber_types::{
+    Command, CoordinateMode, DCode, FunctionCode, GCode, InterpolationMode, Operation, QuadrantMode,
+};
+use log::warn;
+
+use super::error::LayerError;
+use super::layer::{Layer, LayerOptions};
+use super::vec2::Vec2;
+use crate::milling::toolpath::MITER_LIMIT;
+
+/// Below this, cross products are zero: segments are parallel or points aligned.
+const EPSILON: f64 = 1e-9;
+
+/// Shape of an outline generated around the copper.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutlineShape {
+    #[default]
+    BoundingBox,
+    ConvexHull,
+}
+
+/// Board outline used when the project has no outline file, in millimetres.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GeneratedOutline {
+    pub shape: OutlineShape,
+    /// Distance kept between the copper and the board edge.
+    pub margin: f64,
+    pub corner_radius: f64,
+}
+
+impl Default for GeneratedOutline {
+    fn default() -> Self {
+        Self {
+            shape: OutlineShape::default(),
+            margin: 2.0,
+            corner_radius: 0.0,
+        }
+    }
+}
+
+/// Board shape drawn by an outline (profile) file. The edges, lines and arcs drawn in any
+/// order and direction, are chained into closed contours following the center of the
+/// drawn line; contours inside another one are cutouts.
+pub fn board_outline(gerber: &GerberDoc, options: &LayerOptions) -> Result<Paths, LayerError> {
+    let edges = read_edges(gerber, options)?;
+    if edges.is_empty() {
+        return Err(LayerError::EmptyOutline);
+    }
+
+    let contours = chain_edges(edges, options.outline_gap)?;
+    check_intersections(&contours)?;
+
+    let paths: Vec<Path> = contours
+        .into_iter()
+        .map(|contour| contour.into_iter().map(Into::into).collect::<Vec<Point>>())
+        .map(Path::new)
+        .collect();
+
+    // Even-odd filling, so that a contour inside another one is a hole
+    Paths::default()
+        .to_clipper_subject()
+        .add_clip(Paths::new(paths))
+        .union(FillRule::EvenOdd)
+        .map_err(|e| LayerError::Parse {
+            line: None,
+            message: format!("Cannot build the board shape: {e}"),
+        })
+}
+
+/// Polylines drawn by the file, consecutive draws making one polyline. Flashes and
+/// apertures are ignored, only the path of the pen matters.
+fn read_edges(gerber: &GerberDoc, options: &LayerOptions) -> Result<Vec<Vec<Vec2>>, LayerError> {
+    let scale = Layer::gerber_scale(gerber);
+    let mut incremental = Layer::gerber_incremental(gerber);
+
+    let mut edges: Vec<Vec<Vec2>> = Vec::new();
+    let mut drawing = false;
+    let mut current_pos = Vec2::default();
+    let mut current_mode = InterpolationMode::Linear;
+    let mut current_quadrant = QuadrantMode::Multi;
+
+    for (index, cmd) in gerber.commands.iter().enumerate() {
+        let Some(cmd) = Layer::command(cmd)? else {
+            continue;
+        };
+
+        match cmd {
+            Command::FunctionCode(FunctionCode::DCode(DCode::Operation(operation))) => {
+                match operation {
+                    Operation::Move(coordinates) => {
+                        current_pos.set(coordinates, scale, incremental);
+                        drawing = false;
+                    }
+                    Operation::Interpolate(coordinates, coordinate_offset) => {
+                        let mut target = current_pos;
+                        target.set(coordinates, scale, incremental);
+
+                        let points: Vec<Vec2> = match current_mode {
+                            InterpolationMode::Linear if current_pos.distance(&target) == 0.0 => {
+                                continue
+                            }
+                            InterpolationMode::Linear => vec![target],
+                            InterpolationMode::ClockwiseCircular
+                            | InterpolationMode::CounterclockwiseCircular => {
+                                let mut offset: Vec2 = match coordinate_offset {
+                                    Some(offset) => offset.into(),
+                                    None => Vec2::default(),
+                                };
+                                offset.mult(scale);
+
+                                Layer::arc_points(
+                                    current_pos,
+                                    target,
+                                    offset,
+                                    current_mode == InterpolationMode::ClockwiseCircular,
+                                    current_quadrant,
+                                    options.arc_tolerance,
+                                )
+                            }
+                        };
+
+                        match edges.last_mut() {
+                            Some(edge) if drawing => edge.extend(points),
+                            _ => edges.push([vec![current_pos], points].concat()),
+                        }
+                        drawing = true;
+                        current_pos = target;
+                    }
+                    Operation::Flash(coordinates) => {
+                        warn!("Flash in an outline file, command #{index} is ignored");
+                        current_pos.set(coordinates, scale, incremental);
+                        drawing = false;
+                    }
+                }
+            }
+            Command::FunctionCode(FunctionCode::GCode(GCode::InterpolationMode(mode))) => {
+                current_mode = *mode
+            }
+            Command::FunctionCode(FunctionCode::GCode(GCode::QuadrantMode(mode))) => {
+                current_quadrant = *mode
+            }
+            Command::FunctionCode(FunctionCode::GCode(GCode::CoordinateMode(mode))) => {
+                incremental = *mode == CoordinateMode::Incremental
+            }
+            // Apertures, polarity, attributes... don't change the path of the pen
+            _ => (),
+        }
+    }
+
+    Ok(edges)
+}
+
+/// Join polylines end to end into closed contours, ends less than `gap` apart being the
+/// same point. Contours don't repeat their first point at the end.
+fn chain_edges(mut edges: Vec<Vec<Vec2>>, gap: f64) -> Result<Vec<Vec<Vec2>>, LayerError> {
+    let mut contours: Vec<Vec<Vec2>> = Vec::new();
+
+    while let Some(mut contour) = edges.pop() {
+        // The first polyline may be in the middle of the contour, the other way is
+        // followed once an end is found
+        let mut reversed = false;
+
+        loop {
+            let end = contour[contour.len() - 1];
+            if contour.len() > 2 && end.distance(&contour[0]) <= gap {
+                contour.pop();
+                break;
+            }
+
+            let next = edges
+                .iter()
+                .enumerate()
+                .flat_map(|(index, edge)| {
+                    [
+                        (index, false, end.distance(&edge[0])),
+                        (index, true, end.distance(&edge[edge.len() - 1])),
+                    ]
+                })
+                .filter(|(_, _, distance)| *distance <= gap)
+                .min_by(|(_, _, a), (_, _, b)| a.total_cmp(b));
+
+            match next {
+                Some((index, flip, _)) => {
+                    let mut edge = edges.swap_remove(index);
+                    if flip {
+                        edge.reverse();
+                    }
+                    contour.extend(edge.into_iter().skip(1));
+                }
+                None if !reversed => {
+                    contour.reverse();
+                    reversed = true;
+                }
+                None => return Err(LayerError::OpenOutline { x: end.x, y: end.y }),
+            }
+        }
+
+        contours.push(contour);
+    }
+
+    Ok(contours)
+}
+
+/// Fail on the first crossing or touching edges, of one contour or two.
+fn check_intersections(contours: &[Vec<Vec2>]) -> Result<(), LayerError> {
+    // Every edge as (contour, index, start, end), sorted by their left end to only
+    // compare edges overlapping along X
+    let mut edges: Vec<(usize, usize, Vec2, Vec2)> = contours
+        .iter()
+        .enumerate()
+        .flat_map(|(c, contour)| {
+            (0..contour.len()).map(move |i| (c, i, contour[i], contour[(i + 1) % contour.len()]))
+        })
+        .collect();
+    edges.sort_by(|a, b| a.2.x.min(a.3.x).total_cmp(&b.2.x.min(b.3.x)));
+
+    for (i, a) in edges.iter().enumerate() {
+        let right = a.2.x.max(a.3.x);
+
+        for b in edges[i + 1..]
+            .iter()
+            .take_while(|b| b.2.x.min(b.3.x) <= right)
+        {
+            let count = contours[a.0].len();
+            let adjacent = a.0 == b.0 && ((a.1 + 1) % count == b.1 || (b.1 + 1) % count == a.1);
+            if adjacent {
+                continue;
+            }
+
+            if let Some(point) = segment_intersection(a.2, a.3, b.2, b.3) {
+                return Err(LayerError::OutlineIntersection {
+                    x: point.x,
+                    y: point.y,
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// A point shared by segments `a`-`b` and `c`-`d`, if any.
+fn segment_intersection(a: Vec2, b: Vec2, c: Vec2, d: Vec2) -> Option<Vec2> {
+    let cross = |u: Vec2, v: Vec2| u.x * v.y - u.y * v.x;
+    let dot = |u: Vec2, v: Vec2| u.x * v.x + u.y * v.y;
+    let along = |t: f64| {
+        let mut step = b - a;
+        step.mult(t);
+        a + step
+    };
+
+    let r = b - a;
+    let s = d - c;
+    let ac = c - a;
+    let denominator = cross(r, s);
+
+    if denominator.abs() < EPSILON {
+        // Parallel, they only meet when overlapping on the same line
+        if cross(ac, r).abs() >= EPSILON || dot(r, r) < EPSILON {
+            return None;
+        }
+
+        let t0 = dot(ac, r) / dot(r, r);
+        let t1 = t0 + dot(s, r) / dot(r, r);
+        let (first, last) = (t0.min(t1).max(0.0), t0.max(t1).min(1.0));
+        return (first <= last).then(|| along(first));
+    }
+
+    let t = cross(ac, s) / denominator;
+    let u = cross(ac, r) / denominator;
+    ((0.0..=1.0).contains(&t) && (0.0..=1.0).contains(&u)).then(|| along(t))
+}
+
+/// Board shape around `copper`, grown by the margin then with its corners rounded. Corners
+/// are rounded by shrinking the shape by the radius and growing it back with round joins,
+/// the radius being limited to half the smallest side.
+pub fn generated_outline(copper: &Paths, outline: &GeneratedOutline) -> Result<Paths, LayerError> {
+    if copper.is_empty() {
+        return Err(LayerError::NoCopper);
+    }
+
+    let bounds = copper.bounds();
+    let base = match outline.shape {
+        OutlineShape::BoundingBox => Path::new(vec![
+            bounds.min,
+            Point::new(bounds.max.x(), bounds.min.y()),
+            bounds.max,
+            Point::new(bounds.min.x(), bounds.max.y()),
+        ]),
+        OutlineShape::ConvexHull => convex_hull(copper),
+    };
+
+    let shape = Paths::new(vec![base]).inflate(
+        outline.margin,
+        JoinType::Miter,
+        EndType::Polygon,
+        MITER_LIMIT,
+    );
+
+    let size = shape.bounds().size();
+    let radius = outline.corner_radius.min(size.x().min(size.y()) / 2.0);
+    if radius <= 0.0 {
+        return Ok(shape);
+    }
+
+    Ok(shape
+        .inflate(-radius, JoinType::Miter, EndType::Polygon, MITER_LIMIT)
+        .inflate(radius, JoinType::Round, EndType::Polygon, MITER_LIMIT))
+}
+
+/// Smallest convex polygon holding every point of `paths`, counterclockwise (Andrew's
+/// monotone chain).
+fn convex_hull(paths: &Paths) -> Path {
+    let mut points: Vec<Vec2> = paths
+        .iter()
+        .flat_map(|path| path.iter().map(|p| Vec2::new(p.x(), p.y())))
+        .collect();
+    points.sort_by(|a, b| a.x.total_cmp(&b.x).then(a.y.total_cmp(&b.y)));
+
+    // Positive when `o`, `a`, `b` turn left
+    let turn = |o: Vec2, a: Vec2, b: Vec2| (a.x - o.x) * (b.y - o.y) - (a.y - o.y) * (b.x - o.x);
+
+    let mut hull: Vec<Vec2> = Vec::with_capacity(points.len() + 1);
+    for pass in [points.clone(), points.into_iter().rev().collect()] {
+        let start = hull.len();
+        for point in pass {
+            while hull.len() >= start + 2
+                && turn(hull[hull.len() - 2], hull[hull.len() - 1], point) <= EPSILON
+            {
+                hull.pop();
+            }
+            hull.push(point);
+        }
+        // The last point of a chain is the first of the other one
+        hull.pop();
+    }
+
+    Path::new(hull.into_iter().map(Into::into).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufReader;
+
+    use super::*;
+    use crate::layer::layer::DEFAULT_OUTLINE_GAP;
+
+    fn polyline(points: &[(f64, f64)]) -> Vec<Vec2> {
+        points.iter().map(|(x, y)| Vec2::new(*x, *y)).collect()
+    }
+
+    fn contours(edges: Vec<Vec<Vec2>>) -> Result<Vec<Vec<Vec2>>, LayerError> {
+        let contours = chain_edges(edges, DEFAULT_OUTLINE_GAP)?;
+        check_intersections(&contours)?;
+        Ok(contours)
+    }
+
+    #[test]
+    fn rectangle_with_gap_and_mixed_directions() {
+        let edges = vec![
+            polyline(&[(0.0, 0.0), (10.0, 0.0)]),
+            // Drawn backwards
+            polyline(&[(10.0, 5.0), (10.0, 0.0)]),
+            polyline(&[(0.0, 5.0), (10.0, 5.0)]),
+            // Stops short of the first corner
+            polyline(&[(0.0, 5.0), (0.0, 0.03)]),
+        ];
+
+        let contours = contours(edges).unwrap();
+        assert_eq!(contours.len(), 1);
+        assert_eq!(contours[0].len(), 4);
+    }
+
+    #[test]
+    fn open_outline() {
+        let edges = vec![
+            polyline(&[(0.0, 0.0), (10.0, 0.0), (10.0, 5.0)]),
+            polyline(&[(10.0, 5.0), (0.0, 5.0), (0.0, 1.0)]),
+        ];
+
+        assert!(matches!(
+            contours(edges),
+            Err(LayerError::OpenOutline { .. })
+        ));
+    }
+
+    #[test]
+    fn figure_eight() {
+        let edges = vec![polyline(&[
+            (0.0, 0.0),
+            (10.0, 10.0),
+            (10.0, 0.0),
+            (0.0, 10.0),
+            (0.0, 0.0),
+        ])];
+
+        assert!(matches!(
+            contours(edges),
+            Err(LayerError::OutlineIntersection { .. })
+        ));
+    }
+
+    #[test]
+    fn nested_cutout() {
+        let gerber = "%FSLAX24Y24*%\n%MOMM*%\n%ADD10C,0.1*%\nD10*\n\
+            X0Y0D02*\nX100000Y0D01*\nX100000Y100000D01*\nX0Y100000D01*\nX0Y0D01*\n\
+            X40000Y40000D02*\nX40000Y60000D01*\nX60000Y60000D01*\nX60000Y40000D01*\n\
+            X40000Y40000D01*\nM02*\n";
+        let gerber = gerber_parser::parse(BufReader::new(gerber.as_bytes()))
+            .map_err(|(_, e)| e)
+            .unwrap();
+
+        let paths = board_outline(&gerber, &LayerOptions::default()).unwrap();
+        assert_eq!(paths.len(), 2);
+        let area: f64 = paths.iter().map(|path| path.signed_area()).sum();
+        assert!((area - 96.0).abs() < 1e-6);
+    }
+}
@@ -1,4 +1,4 @@
-use gerber_types::Coordinates;
+use gerber_types::{CoordinateOffset, Coordinates};
 
 #[derive(Debug, Default, Clone, Copy)]
 pub struct Vec2 {
@@ -19,6 +19,14 @@ impl Vec2 {
         }
     }
 
+    pub fn length(&self) -> f64 {
+        (self.x * self.x + self.y * self.y).sqrt()
+    }
+
+    pub fn distance(&self, other: &Vec2) -> f64 {
+        (*other - *self).length()
+    }
+
     pub fn mult(&mut self, scalar: f64) {
         self.x *= scalar;
         self.y *= scalar;
@@ -29,13 +37,28 @@ impl Vec2 {
         self.y *= -1.0;
     }
 
-    pub fn set(&mut self, coord: &Coordinates) {
-        if let Some(x) = coord.x {
-            self.x = x.into();
+    /// Rotate counterclockwise around the origin.
+    pub fn rotated(&self, radians: f64) -> Self {
+        let (sin, cos) = radians.sin_cos();
+        Self {
+            x: self.x * cos - self.y * sin,
+            y: self.x * sin + self.y * cos,
         }
+    }
 
-        if let Some(y) = coord.y {
-            self.y = y.into();
+    /// Update the axes present in `coord`, missing ones keep their current value. Values
+    /// are multiplied by `scale`, and added to the current ones in incremental notation.
+    pub fn set(&mut self, coord: &Option<Coordinates>, scale: f64, incremental: bool) {
+        if let Some(coord) = coord {
+            if let Some(x) = coord.x {
+                let x = f64::from(x) * scale;
+                self.x = if incremental { self.x + x } else { x };
+            }
+
+            if let Some(y) = coord.y {
+                let y = f64::from(y) * scale;
+                self.y = if incremental { self.y + y } else { y };
+            }
         }
     }
 }
@@ -51,6 +74,17 @@ impl std::ops::Add for Vec2 {
     }
 }
 
+impl std::ops::Sub for Vec2 {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        Self::Output {
+            x: self.x - rhs.x,
+            y: self.y - rhs.y,
+        }
+    }
+}
+
 impl Into<clipper2::Point> for Vec2 {
     fn into(self) -> clipper2::Point {
         clipper2::Point::new(self.x, self.y)
@@ -65,3 +99,12 @@ impl From<&Coordinates> for Vec2 {
         }
     }
 }
+
+impl From<&CoordinateOffset> for Vec2 {
+    fn from(coord: &CoordinateOffset) -> Self {
+        Self {
+            x: if let Some(x) = coord.x { x.into() } else { 0.0 },
+            y: if let Some(y) = coord.y { y.into() } else { 0.0 },
+        }
+    }
+}
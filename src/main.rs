@@ -14,7 +14,10 @@ use crate::ui::message::{AppMessage, MainWindowMessage};
 
 mod app_logger;
 mod base_window;
+mod drill;
 mod layer;
+mod milling;
+mod project;
 mod ui;
 
 pub const VERSION_APP: &str = env!("BUILD_VERSION");
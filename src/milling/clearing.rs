@@ -0,0 +1,141 @@
+use clipper2::{EndType, FillRule, JoinType, Paths};
+use log::error;
+
+use super::toolpath::{Cut, Operation, Toolpath, MAX_OVERLAP, MITER_LIMIT};
+use crate::layer::{layer::Layer, layer::DEFAULT_ARC_TOLERANCE, vec2::Vec2};
+
+/// How the area to clear is filled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ClearingPattern {
+    /// Contours of the area, each one further inside
+    #[default]
+    Concentric,
+    /// Parallel lines along the X axis, then one contour of the area
+    Zigzag,
+}
+
+/// Copper clearing settings, in millimetres.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClearingOptions {
+    pub pattern: ClearingPattern,
+    /// Cutting width of the endmill.
+    pub tool_diameter: f64,
+    /// Part of the tool width cut again by the next pass, from 0 to 1.
+    pub overlap: f64,
+    /// Copper kept around tracks and pads, from the copper edge to the cleared area.
+    pub margin: f64,
+}
+
+impl Default for ClearingOptions {
+    fn default() -> Self {
+        Self {
+            pattern: ClearingPattern::default(),
+            tool_diameter: 0.8,
+            overlap: 0.4,
+            margin: 0.2,
+        }
+    }
+}
+
+/// Cuts removing the copper of the `board` that is not part of `layer`, at least
+/// `margin` away from it. The tool never cuts outside the board outline, so a board
+/// without outline has nothing to clear.
+///
+/// Areas closed by copper, like the inside of a ground ring, are cleared as well, and the
+/// copper islands inside an area are gone around.
+pub fn clearing(layer: &Layer, board: &Layer, options: &ClearingOptions) -> Toolpath {
+    let mut toolpath = Toolpath::new(Operation::Clearing, options.tool_diameter);
+    if layer.empty() || board.empty() {
+        return toolpath;
+    }
+
+    let keepout = layer.paths().inflate(
+        options.margin,
+        JoinType::Round,
+        EndType::Polygon,
+        MITER_LIMIT,
+    );
+    let area = match board
+        .paths()
+        .to_clipper_subject()
+        .add_clip(keepout)
+        .difference(FillRule::default())
+    {
+        Ok(area) => area,
+        Err(e) => {
+            error!("Cannot compute the area to clear: {e}");
+            return toolpath;
+        }
+    };
+
+    let step = options.tool_diameter * (1.0 - options.overlap.clamp(0.0, MAX_OVERLAP));
+    // The passes would never reach the middle of the area
+    if step <= 0.0 {
+        error!("Invalid clearing tool diameter: {}", options.tool_diameter);
+        return toolpath;
+    }
+    let contour = |offset: f64| {
+        area.inflate(-offset, JoinType::Round, EndType::Polygon, MITER_LIMIT)
+            .simplify(DEFAULT_ARC_TOLERANCE, false)
+    };
+
+    match options.pattern {
+        ClearingPattern::Concentric => {
+            // From the edges of the area to its middle
+            for pass in 0.. {
+                let contours = contour(options.tool_diameter / 2.0 + step * pass as f64);
+                if contours.is_empty() {
+                    break;
+                }
+
+                toolpath.extend(contours);
+            }
+        }
+        ClearingPattern::Zigzag => {
+            // The contour where the tool can go, the lines only reach it
+            let contours = contour(options.tool_diameter / 2.0);
+            toolpath.extend_cuts(scanlines(&contours, step));
+            toolpath.extend(contours);
+        }
+    }
+
+    toolpath
+}
+
+/// Parts of horizontal lines `step` apart inside `paths`, filled with the even-odd rule.
+fn scanlines(paths: &Paths, step: f64) -> Vec<Cut> {
+    if paths.is_empty() {
+        return Vec::new();
+    }
+
+    let bounds = paths.bounds();
+    let rows = (bounds.size().y() / step).floor() as usize + 1;
+    let mut cuts = Vec::new();
+
+    for row in 0..rows {
+        let y = bounds.min.y() + step * (row as f64 + 0.5);
+
+        // Where the edges cross the line, each pair bounding a part inside
+        let mut crossings: Vec<f64> = paths
+            .iter()
+            .flat_map(|path| {
+                let points: Vec<Vec2> = path.iter().map(|p| Vec2::new(p.x(), p.y())).collect();
+                (0..points.len())
+                    .map(move |i| (points[i], points[(i + 1) % points.len()]))
+                    .filter(|(from, to)| (from.y <= y) != (to.y <= y))
+                    .map(|(from, to)| from.x + (y - from.y) * (to.x - from.x) / (to.y - from.y))
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        crossings.sort_by(f64::total_cmp);
+
+        // Chained from the nearest end when added to the toolpath, lines alternate
+        // directions by themselves
+        cuts.extend(crossings.chunks_exact(2).map(|pair| Cut {
+            points: vec![Vec2::new(pair[0], y), Vec2::new(pair[1], y)],
+            closed: false,
+        }));
+    }
+
+    cuts
+}
@@ -0,0 +1,186 @@
+use clipper2::{EndType, FillRule, JoinType, Path, Paths};
+use log::error;
+
+use super::region::Region;
+use super::toolpath::{Cut, Operation, Toolpath, MITER_LIMIT};
+use super::voronoi::voronoi_isolation;
+use crate::layer::{layer::Layer, layer::DEFAULT_ARC_TOLERANCE, vec2::Vec2};
+
+/// Copper left by a tool thinner than this is not cut again by the next tools, it comes
+/// from the rounding of the contours.
+const MIN_REST_WIDTH: f64 = 0.02;
+
+/// Distance between the points of a contour tested against the copper left to cut.
+const REST_SAMPLING_STEP: f64 = 0.05;
+
+/// Most contours cut around the copper.
+pub const MAX_PASSES: u32 = 20;
+
+/// How the copper is isolated.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IsolationMode {
+    /// Contours following the copper edges, at a tool radius from them
+    #[default]
+    Offset,
+    /// One cut halfway between distinct copper polygons
+    Voronoi,
+}
+
+/// Isolation milling settings, in millimetres.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IsolationOptions {
+    pub mode: IsolationMode,
+    /// Cutting width of each tool at the milling depth, in cutting order: the largest
+    /// first, the next ones only cut where the previous ones could not go. Voronoi
+    /// isolation cuts with the last one.
+    pub tool_diameters: Vec<f64>,
+    /// Number of contours cut around the copper by the first tool, each one further away.
+    /// Voronoi isolation always makes one.
+    pub passes: u32,
+    /// Part of the tool width cut again by the next pass, from 0 to 1.
+    pub overlap: f64,
+}
+
+impl Default for IsolationOptions {
+    fn default() -> Self {
+        Self {
+            mode: IsolationMode::default(),
+            tool_diameters: vec![0.2],
+            passes: 1,
+            overlap: 0.25,
+        }
+    }
+}
+
+/// Cuts isolating the copper of `layer` from each other, inside the `board` outline for
+/// the Voronoi mode. One toolpath per tool with something to cut, in cutting order.
+pub fn isolation(layer: &Layer, board: &Layer, options: &IsolationOptions) -> Vec<Toolpath> {
+    let toolpaths = match options.mode {
+        IsolationMode::Offset => offset_isolation(layer, options),
+        IsolationMode::Voronoi => options
+            .tool_diameters
+            .last()
+            .map(|tool| voronoi_isolation(layer.paths(), board.paths(), *tool))
+            .into_iter()
+            .collect(),
+    };
+
+    toolpaths
+        .into_iter()
+        .filter(|toolpath| !toolpath.empty())
+        .collect()
+}
+
+/// Contours isolating the copper of `layer`, innermost pass first. The first pass is the
+/// copper grown by half the tool diameter, so that the edge of the tool runs along the
+/// edge of the copper; each next one is `tool_diameter * (1 - overlap)` further. A pass
+/// offsets the whole copper at once: where the passes of two tracks meet they merge into
+/// one contour instead of crossing and cutting the same groove twice.
+///
+/// The passes of the first tool set the width isolated around the copper. Where a tool
+/// is too large to go, between close pads for instance, copper is left: the next tool
+/// makes the contours covering the same width, but only keeps their parts reaching the
+/// copper left, the isolated area minus the areas cut by the previous tools.
+fn offset_isolation(layer: &Layer, options: &IsolationOptions) -> Vec<Toolpath> {
+    let copper = layer.paths();
+    let Some(first) = options.tool_diameters.first() else {
+        return Vec::new();
+    };
+    if copper.is_empty() {
+        return Vec::new();
+    }
+
+    let width = first + first * (1.0 - options.overlap) * options.passes.saturating_sub(1) as f64;
+    let isolated = copper.inflate(width, JoinType::Round, EndType::Polygon, MITER_LIMIT);
+    let Some(mut rest) = difference(&isolated, copper) else {
+        return Vec::new();
+    };
+
+    let mut toolpaths = Vec::new();
+    for &tool in options.tool_diameters.iter() {
+        let step = tool * (1.0 - options.overlap);
+        let mut toolpath = Toolpath::new(Operation::Isolation, tool);
+
+        // Where the tool touches the copper left, slivers of it aside
+        let reach = Region::new(
+            &rest
+                .inflate(
+                    -MIN_REST_WIDTH / 2.0,
+                    JoinType::Round,
+                    EndType::Polygon,
+                    MITER_LIMIT,
+                )
+                .inflate(
+                    (MIN_REST_WIDTH + tool) / 2.0,
+                    JoinType::Round,
+                    EndType::Polygon,
+                    MITER_LIMIT,
+                ),
+        );
+
+        let mut offset = tool / 2.0;
+        while offset <= width - tool / 2.0 + DEFAULT_ARC_TOLERANCE {
+            let contours = copper
+                .inflate(offset, JoinType::Round, EndType::Polygon, MITER_LIMIT)
+                .simplify(DEFAULT_ARC_TOLERANCE, false);
+
+            toolpath.extend_cuts(
+                contours
+                    .iter()
+                    .map(|path| Cut {
+                        points: path.iter().map(|p| Vec2::new(p.x(), p.y())).collect(),
+                        closed: true,
+                    })
+                    .flat_map(|cut| reach.clip(&cut, REST_SAMPLING_STEP))
+                    .map(|cut| cut.simplified(DEFAULT_ARC_TOLERANCE)),
+            );
+
+            if step <= 0.0 {
+                break;
+            }
+            offset += step;
+        }
+
+        match difference(&rest, &swept(&toolpath)) {
+            Some(left) => rest = left,
+            None => break,
+        }
+        toolpaths.push(toolpath);
+    }
+
+    toolpaths
+}
+
+/// Area cut by the tool following `toolpath`, overlapping where the passes do.
+fn swept(toolpath: &Toolpath) -> Paths {
+    let radius = toolpath.tool_diameter / 2.0;
+    let paths = |closed: bool| {
+        Paths::new(
+            toolpath
+                .cuts()
+                .iter()
+                .filter(|cut| cut.closed == closed)
+                .map(|cut| Path::new(cut.points.iter().map(|p| (*p).into()).collect()))
+                .collect(),
+        )
+    };
+
+    let closed = paths(true).inflate(radius, JoinType::Round, EndType::Joined, MITER_LIMIT);
+    let open = paths(false).inflate(radius, JoinType::Round, EndType::Round, MITER_LIMIT);
+    Paths::new(closed.iter().chain(open.iter()).cloned().collect())
+}
+
+/// Non-zero filling: the areas swept by overlapping passes are cut, not cancelled out.
+fn difference(subject: &Paths, clip: &Paths) -> Option<Paths> {
+    match subject
+        .to_clipper_subject()
+        .add_clip(clip.clone())
+        .difference(FillRule::NonZero)
+    {
+        Ok(paths) => Some(paths),
+        Err(e) => {
+            error!("Cannot compute the copper left to isolate: {e}");
+            None
+        }
+    }
+}
@@ -0,0 +1,5 @@
+pub mod clearing;
+pub mod isolation;
+pub mod region;
+pub mod toolpath;
+pub mod voronoi;
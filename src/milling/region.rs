@@ -0,0 +1,120 @@
+use clipper2::{Bounds, Path, Paths, PointInPolygonResult};
+
+use super::toolpath::Cut;
+use crate::layer::vec2::Vec2;
+
+/// Bisection steps finding where a segment leaves a region.
+const EXIT_STEPS: u32 = 24;
+
+/// Area covered by paths with the even-odd rule: inside an outline and outside its holes.
+///
+/// Boolean operations of the clipper drop open paths, cuts are clipped with this instead.
+pub struct Region {
+    paths: Vec<(Path, Bounds)>,
+}
+
+impl Region {
+    pub fn new(paths: &Paths) -> Self {
+        Self {
+            paths: paths
+                .iter()
+                .map(|path| (path.clone(), path.bounds()))
+                .collect(),
+        }
+    }
+
+    pub fn contains(&self, point: Vec2) -> bool {
+        let inside = self
+            .paths
+            .iter()
+            .filter(|(_, bounds)| {
+                point.x >= bounds.min.x()
+                    && point.x <= bounds.max.x()
+                    && point.y >= bounds.min.y()
+                    && point.y <= bounds.max.y()
+            })
+            .filter(|(path, _)| {
+                path.is_point_inside(point.into()) != PointInPolygonResult::IsOutside
+            })
+            .count();
+
+        inside % 2 == 1
+    }
+
+    /// Where the segment from `inside` to `outside` leaves the region.
+    pub fn exit(&self, inside: Vec2, outside: Vec2) -> Vec2 {
+        let (mut inside, mut outside) = (inside, outside);
+        for _ in 0..EXIT_STEPS {
+            let mut middle = outside - inside;
+            middle.mult(0.5);
+            let middle = inside + middle;
+
+            if self.contains(middle) {
+                inside = middle;
+            } else {
+                outside = middle;
+            }
+        }
+
+        inside
+    }
+
+    /// Parts of `cut` inside the region. The cut is tested every `step` along its
+    /// segments, a part of the region thinner than that may be missed.
+    pub fn clip(&self, cut: &Cut, step: f64) -> Vec<Cut> {
+        let mut points = cut.points.clone();
+        if cut.closed {
+            points.extend(cut.points.first().copied());
+        }
+
+        // Segments split so that no step crosses the region unseen
+        let mut samples: Vec<Vec2> = points.first().copied().into_iter().collect();
+        for pair in points.windows(2) {
+            let count = (pair[0].distance(&pair[1]) / step).ceil().max(1.0) as usize;
+            samples.extend((1..=count).map(|i| {
+                let mut delta = pair[1] - pair[0];
+                delta.mult(i as f64 / count as f64);
+                pair[0] + delta
+            }));
+        }
+
+        let inside: Vec<bool> = samples.iter().map(|point| self.contains(*point)).collect();
+        if inside.iter().all(|inside| *inside) {
+            return vec![cut.clone()];
+        }
+
+        let mut parts: Vec<Vec<Vec2>> = Vec::new();
+        let mut current: Option<Vec<Vec2>> = inside[0].then(|| vec![samples[0]]);
+        for i in 1..samples.len() {
+            match (inside[i - 1], inside[i], current.as_mut()) {
+                (_, true, Some(part)) => part.push(samples[i]),
+                (_, true, None) => {
+                    current = Some(vec![self.exit(samples[i], samples[i - 1]), samples[i]])
+                }
+                (true, false, Some(part)) => {
+                    part.push(self.exit(samples[i - 1], samples[i]));
+                    parts.extend(current.take());
+                }
+                _ => (),
+            }
+        }
+        parts.extend(current);
+
+        // A closed cut starting inside ends where its first part begins
+        if cut.closed && inside[0] && parts.len() > 1 {
+            let first = parts.remove(0);
+            if let Some(last) = parts.last_mut() {
+                last.extend(first.into_iter().skip(1));
+            }
+        }
+
+        parts
+            .into_iter()
+            .filter(|part| part.len() > 1)
+            .map(|points| Cut {
+                points,
+                closed: false,
+            })
+            .collect()
+    }
+}
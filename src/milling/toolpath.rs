@@ -0,0 +1,211 @@
+use std::fmt::Display;
+
+use clipper2::Paths;
+use iced::widget::canvas::{stroke, Frame, Path as IcedPath, Stroke};
+use iced::{Color, Point as IcedPoint};
+
+use crate::layer::vec2::Vec2;
+
+/// Thinnest endmill accepted, in millimetres.
+pub const MIN_TOOL_DIAMETER: f64 = 0.01;
+
+/// Offset corners sharper than this miter ratio are squared. Only miter joins use it:
+/// the generated board outline has some, toolpath contours have round ones.
+pub const MITER_LIMIT: f64 = 2.0;
+
+/// Largest part of the tool width cut again by the next pass, so that each pass goes
+/// further than the previous one.
+pub const MAX_OVERLAP: f64 = 0.9;
+
+/// What a toolpath is cut for, in cutting order.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Operation {
+    #[default]
+    Isolation,
+    Clearing,
+}
+
+impl Display for Operation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Operation::Isolation => write!(f, "isolation"),
+            Operation::Clearing => write!(f, "clearing"),
+        }
+    }
+}
+
+/// Polyline followed by the tool without lifting it. A closed cut goes back to its first
+/// point after the last one.
+#[derive(Debug, Clone)]
+pub struct Cut {
+    pub points: Vec<Vec2>,
+    pub closed: bool,
+}
+
+impl Cut {
+    pub fn length(&self) -> f64 {
+        let segments = self
+            .points
+            .windows(2)
+            .map(|pair| pair[0].distance(&pair[1]));
+        let closing = match (self.closed, self.points.first(), self.points.last()) {
+            (true, Some(first), Some(last)) => last.distance(first),
+            _ => 0.0,
+        };
+
+        segments.sum::<f64>() + closing
+    }
+
+    /// The cut through fewer of its points, at most `tolerance` away from them
+    /// (Ramer-Douglas-Peucker). The ends are kept.
+    pub fn simplified(self, tolerance: f64) -> Self {
+        Self {
+            points: simplify(&self.points, tolerance),
+            closed: self.closed,
+        }
+    }
+}
+
+fn simplify(points: &[Vec2], tolerance: f64) -> Vec<Vec2> {
+    let (Some(&first), Some(&last)) = (points.first(), points.last()) else {
+        return Vec::new();
+    };
+
+    let chord = last - first;
+    let farthest = points
+        .iter()
+        .enumerate()
+        .map(|(index, point)| {
+            let offset = *point - first;
+            let distance = if chord.length() < f64::EPSILON {
+                offset.length()
+            } else {
+                (chord.x * offset.y - chord.y * offset.x).abs() / chord.length()
+            };
+            (index, distance)
+        })
+        .max_by(|(_, a), (_, b)| a.total_cmp(b));
+
+    match farthest {
+        Some((index, distance)) if distance > tolerance => {
+            let mut simplified = simplify(&points[..=index], tolerance);
+            simplified.pop();
+            simplified.extend(simplify(&points[index..], tolerance));
+            simplified
+        }
+        _ if points.len() > 1 => vec![first, last],
+        _ => vec![first],
+    }
+}
+
+/// Cuts made by one tool, in millimetres and in cutting order.
+#[derive(Debug, Default, Clone)]
+pub struct Toolpath {
+    pub operation: Operation,
+    pub tool_diameter: f64,
+    cuts: Vec<Cut>,
+}
+
+impl Toolpath {
+    pub fn new(operation: Operation, tool_diameter: f64) -> Self {
+        Self {
+            operation,
+            tool_diameter,
+            cuts: Vec::new(),
+        }
+    }
+
+    /// Cut closed `contours` after the cuts already in the toolpath.
+    pub fn extend(&mut self, contours: Paths) {
+        self.extend_cuts(contours.iter().map(|path| Cut {
+            points: path.iter().map(|p| Vec2::new(p.x(), p.y())).collect(),
+            closed: true,
+        }));
+    }
+
+    /// Cut `cuts` after the ones already in the toolpath. They are chained from where the
+    /// tool stopped, the board origin at first: the nearest cut is made first, a closed
+    /// one starting on its point nearest to the tool, an open one from its nearest end.
+    pub fn extend_cuts(&mut self, cuts: impl IntoIterator<Item = Cut>) {
+        let mut remaining: Vec<Cut> = cuts
+            .into_iter()
+            .filter(|cut| cut.points.len() > 1)
+            .collect();
+        let mut position = self.end().unwrap_or_default();
+
+        while let Some((index, start, _)) = remaining
+            .iter()
+            .enumerate()
+            .flat_map(|(index, cut)| {
+                let last = cut.points.len() - 1;
+                cut.points
+                    .iter()
+                    .enumerate()
+                    .filter(move |(start, _)| cut.closed || *start == 0 || *start == last)
+                    .map(move |(start, point)| (index, start, position.distance(point)))
+            })
+            .min_by(|(_, _, a), (_, _, b)| a.total_cmp(b))
+        {
+            let mut cut = remaining.swap_remove(index);
+            if cut.closed {
+                cut.points.rotate_left(start);
+            } else if start != 0 {
+                cut.points.reverse();
+            }
+
+            self.cuts.push(cut);
+            position = self.end().unwrap_or_default();
+        }
+    }
+
+    pub fn cuts(&self) -> &[Cut] {
+        &self.cuts
+    }
+
+    pub fn empty(&self) -> bool {
+        self.cuts.is_empty()
+    }
+
+    /// Total distance cut by the tool, travel moves between cuts excluded.
+    pub fn length(&self) -> f64 {
+        self.cuts.iter().map(Cut::length).sum()
+    }
+
+    /// Where the tool is once every cut is made.
+    fn end(&self) -> Option<Vec2> {
+        let cut = self.cuts.last()?;
+        if cut.closed {
+            cut.points.first().copied()
+        } else {
+            cut.points.last().copied()
+        }
+    }
+
+    pub fn draw(&self, frame: &mut Frame, color: Color) {
+        for cut in self.cuts.iter() {
+            let iced_path = IcedPath::new(|b| {
+                let mut points = cut
+                    .points
+                    .iter()
+                    .map(|p| IcedPoint::new(p.x as f32, p.y as f32));
+
+                if let Some(first) = points.next() {
+                    b.move_to(first);
+                    points.for_each(|point| b.line_to(point));
+                    if cut.closed {
+                        b.close();
+                    }
+                }
+            });
+
+            frame.stroke(
+                &iced_path,
+                Stroke {
+                    style: stroke::Style::Solid(color),
+                    width: 1.0,
+                    ..Default::default()
+                },
+            );
+        }
+    }
+}
@@ -0,0 +1,452 @@
+use std::collections::{HashMap, HashSet};
+
+use clipper2::{Bounds, Path, Paths};
+
+use super::region::Region;
+use super::toolpath::{Cut, Operation, Toolpath};
+use crate::layer::{layer::DEFAULT_ARC_TOLERANCE, vec2::Vec2};
+
+/// Largest distance between two points sampled along the copper edges.
+const SAMPLING_STEP: f64 = 0.1;
+
+/// Below this, points are the same, or aligned.
+const EPSILON: f64 = 1e-9;
+
+/// The triangle enclosing every point at the start of the triangulation is this many times
+/// larger than them.
+const SUPER_TRIANGLE_SCALE: f64 = 20.0;
+
+/// One cut midway between distinct copper polygons, each net being isolated at the largest
+/// possible distance from the others. Cuts are kept inside `board`, or the copper bounding
+/// box grown by the tool diameter when there is no outline.
+///
+/// The copper edges are sampled into points labeled by their polygon, a hole belonging to
+/// the polygon around it. In the Delaunay triangulation of these points, every edge joining
+/// two polygons is crossed by the Voronoi edge between the circumcenters of its triangles:
+/// these Voronoi edges make the cuts.
+pub fn voronoi_isolation(copper: &Paths, board: &Paths, tool_diameter: f64) -> Toolpath {
+    let mut toolpath = Toolpath::new(Operation::Isolation, tool_diameter);
+
+    let (points, labels) = sample_copper(copper);
+    if labels.iter().collect::<HashSet<_>>().len() < 2 {
+        return toolpath;
+    }
+
+    let board = if board.is_empty() {
+        let bounds = copper.bounds();
+        Paths::new(vec![Path::rectangle(
+            bounds.min.x() - tool_diameter,
+            bounds.min.y() - tool_diameter,
+            bounds.size().x() + 2.0 * tool_diameter,
+            bounds.size().y() + 2.0 * tool_diameter,
+        )])
+    } else {
+        board.clone()
+    };
+
+    let triangulation = Triangulation::new(points, &board.bounds());
+    let region = Region::new(&board);
+    let mut graph = Graph::default();
+
+    for (t, triangle) in triangulation.triangles.iter().enumerate() {
+        if !triangle.alive {
+            continue;
+        }
+
+        for i in 0..3 {
+            let Some(u) = triangle.neighbors[i] else {
+                continue;
+            };
+            let a = triangle.vertices[(i + 1) % 3];
+            let b = triangle.vertices[(i + 2) % 3];
+            // Each edge once, between points of two polygons
+            if t > u || a >= labels.len() || b >= labels.len() || labels[a] == labels[b] {
+                continue;
+            }
+
+            if let (Some(from), Some(to)) =
+                (triangulation.circumcenter(t), triangulation.circumcenter(u))
+            {
+                graph.add_clipped(from, to, &region);
+            }
+        }
+    }
+
+    toolpath.extend_cuts(graph.into_cuts());
+    toolpath
+}
+
+/// Points along the edges of every copper path, at most `SAMPLING_STEP` apart, with the
+/// index of the copper polygon each one belongs to.
+fn sample_copper(copper: &Paths) -> (Vec<Vec2>, Vec<usize>) {
+    let paths: Vec<Vec<Vec2>> = copper
+        .iter()
+        .map(|path| path.iter().map(|p| Vec2::new(p.x(), p.y())).collect())
+        .collect();
+    let areas: Vec<f64> = copper.iter().map(Path::signed_area).collect();
+    let regions: Vec<Region> = copper
+        .iter()
+        .map(|path| Region::new(&Paths::new(vec![path.clone()])))
+        .collect();
+
+    let mut samples: Vec<(Vec2, usize)> = Vec::new();
+    for (index, path) in paths.iter().enumerate() {
+        if path.is_empty() {
+            continue;
+        }
+
+        // Holes have a negative area, they belong to the smallest polygon around them
+        let label = if areas[index] >= 0.0 {
+            index
+        } else {
+            (0..paths.len())
+                .filter(|other| areas[*other] > 0.0 && regions[*other].contains(path[0]))
+                .min_by(|a, b| areas[*a].total_cmp(&areas[*b]))
+                .unwrap_or(index)
+        };
+
+        for i in 0..path.len() {
+            let from = path[i];
+            let to = path[(i + 1) % path.len()];
+            let steps = (from.distance(&to) / SAMPLING_STEP).ceil().max(1.0) as usize;
+
+            samples.extend((0..steps).map(|step| {
+                let mut delta = to - from;
+                delta.mult(step as f64 / steps as f64);
+                (from + delta, label)
+            }));
+        }
+    }
+
+    // Inserted row by row, alternating directions, so that each point is next to the
+    // previous one in the triangulation
+    let rows = (samples.len() as f64).sqrt().max(1.0);
+    let bounds = copper.bounds();
+    let row_height = (bounds.size().y() / rows).max(EPSILON);
+    let row = |point: &Vec2| ((point.y - bounds.min.y()) / row_height) as i64;
+    samples.sort_by(|(a, _), (b, _)| {
+        let (row_a, row_b) = (row(a), row(b));
+        row_a.cmp(&row_b).then(if row_a % 2 == 0 {
+            a.x.total_cmp(&b.x)
+        } else {
+            b.x.total_cmp(&a.x)
+        })
+    });
+
+    let mut seen: HashSet<(i64, i64)> = HashSet::new();
+    samples.retain(|(point, _)| seen.insert(key(*point)));
+
+    samples.into_iter().unzip()
+}
+
+/// Voronoi edges. Cocircular points make several triangles share a circumcenter, so the
+/// ends of the edges are merged by position.
+#[derive(Default)]
+struct Graph {
+    nodes: Vec<Vec2>,
+    ids: HashMap<(i64, i64), usize>,
+    edges: Vec<(usize, usize)>,
+}
+
+impl Graph {
+    /// Add the part of the edge `from`-`to` inside `region`. An edge with both ends
+    /// outside is dropped, even if it crosses the region.
+    fn add_clipped(&mut self, from: Vec2, to: Vec2, region: &Region) {
+        let (from, to) = match (region.contains(from), region.contains(to)) {
+            (true, true) => (from, to),
+            (false, false) => return,
+            (true, false) => (from, region.exit(from, to)),
+            (false, true) => (region.exit(to, from), to),
+        };
+
+        let (from, to) = (self.node(from), self.node(to));
+        if from != to {
+            self.edges.push((from, to));
+        }
+    }
+
+    fn node(&mut self, point: Vec2) -> usize {
+        *self.ids.entry(key(point)).or_insert_with(|| {
+            self.nodes.push(point);
+            self.nodes.len() - 1
+        })
+    }
+
+    /// Chain the edges into polylines, ending where three edges or more meet. Loops with
+    /// no such junction are closed cuts.
+    fn into_cuts(self) -> Vec<Cut> {
+        let mut incident: HashMap<usize, Vec<usize>> = HashMap::new();
+        for (index, (from, to)) in self.edges.iter().enumerate() {
+            incident.entry(*from).or_default().push(index);
+            incident.entry(*to).or_default().push(index);
+        }
+
+        let mut used = vec![false; self.edges.len()];
+        let other = |edge: usize, node: usize| {
+            let (from, to) = self.edges[edge];
+            if from == node {
+                to
+            } else {
+                from
+            }
+        };
+        let simplified = |points: Vec<Vec2>, closed: bool| {
+            Cut { points, closed }.simplified(DEFAULT_ARC_TOLERANCE)
+        };
+
+        let mut ends: Vec<usize> = incident
+            .iter()
+            .filter(|(_, edges)| edges.len() != 2)
+            .map(|(node, _)| *node)
+            .collect();
+        ends.sort_unstable();
+
+        let mut cuts: Vec<Cut> = Vec::new();
+        for end in ends {
+            for &first in &incident[&end] {
+                if used[first] {
+                    continue;
+                }
+
+                let mut points = vec![self.nodes[end]];
+                let (mut node, mut edge) = (end, first);
+                loop {
+                    used[edge] = true;
+                    node = other(edge, node);
+                    points.push(self.nodes[node]);
+
+                    match incident[&node].as_slice() {
+                        [a, _] if !used[*a] => edge = *a,
+                        [_, b] if !used[*b] => edge = *b,
+                        _ => break,
+                    }
+                }
+
+                cuts.push(simplified(points, false));
+            }
+        }
+
+        // What is left are loops
+        for first in 0..self.edges.len() {
+            if used[first] {
+                continue;
+            }
+
+            let start = self.edges[first].0;
+            let mut points = vec![self.nodes[start]];
+            let (mut node, mut edge) = (start, first);
+            loop {
+                used[edge] = true;
+                node = other(edge, node);
+                if node == start {
+                    break;
+                }
+                points.push(self.nodes[node]);
+
+                match incident[&node].iter().find(|edge| !used[**edge]) {
+                    Some(next) => edge = *next,
+                    None => break,
+                }
+            }
+
+            cuts.push(simplified(points, true));
+        }
+
+        cuts
+    }
+}
+
+struct Triangle {
+    /// Counterclockwise
+    vertices: [usize; 3],
+    /// Triangle across the edge opposite to each vertex
+    neighbors: [Option<usize>; 3],
+    /// Triangles replaced by later insertions are kept, but dead
+    alive: bool,
+}
+
+/// Delaunay triangulation built by inserting points one by one (Bowyer-Watson). The last
+/// three points are the vertices of a triangle enclosing all the others.
+struct Triangulation {
+    points: Vec<Vec2>,
+    triangles: Vec<Triangle>,
+    /// Where the search of the triangle holding the next point starts
+    last: usize,
+}
+
+impl Triangulation {
+    /// Triangulation of `points`, the super triangle also enclosing `bounds`.
+    fn new(points: Vec<Vec2>, bounds: &Bounds) -> Self {
+        let count = points.len();
+        let (mut min, mut max) = (
+            Vec2::new(bounds.min.x(), bounds.min.y()),
+            Vec2::new(bounds.max.x(), bounds.max.y()),
+        );
+        for point in points.iter() {
+            min = Vec2::new(min.x.min(point.x), min.y.min(point.y));
+            max = Vec2::new(max.x.max(point.x), max.y.max(point.y));
+        }
+        let size = (max.x - min.x).max(max.y - min.y).max(1.0) * SUPER_TRIANGLE_SCALE;
+        let center = Vec2::new((min.x + max.x) / 2.0, (min.y + max.y) / 2.0);
+
+        let mut triangulation = Self {
+            points,
+            triangles: vec![Triangle {
+                vertices: [count, count + 1, count + 2],
+                neighbors: [None; 3],
+                alive: true,
+            }],
+            last: 0,
+        };
+        triangulation.points.extend([
+            center + Vec2::new(-size, -size),
+            center + Vec2::new(size, -size),
+            center + Vec2::new(0.0, size),
+        ]);
+
+        for index in 0..count {
+            triangulation.insert(index);
+        }
+
+        triangulation
+    }
+
+    fn insert(&mut self, index: usize) {
+        let point = self.points[index];
+        let Some(start) = self.locate(point) else {
+            return;
+        };
+
+        // Triangles whose circumcircle holds the point make a cavity around it
+        let mut cavity: HashSet<usize> = HashSet::from([start]);
+        let mut stack = vec![start];
+        while let Some(t) = stack.pop() {
+            for neighbor in self.triangles[t].neighbors.into_iter().flatten() {
+                if !cavity.contains(&neighbor) && self.in_circumcircle(neighbor, point) {
+                    cavity.insert(neighbor);
+                    stack.push(neighbor);
+                }
+            }
+        }
+
+        // Edges around the cavity, with the triangle outside of it
+        let mut boundary: Vec<(usize, usize, Option<usize>, usize)> = Vec::new();
+        for &t in cavity.iter() {
+            let triangle = &self.triangles[t];
+            for i in 0..3 {
+                match triangle.neighbors[i] {
+                    Some(neighbor) if cavity.contains(&neighbor) => (),
+                    outside => boundary.push((
+                        triangle.vertices[(i + 1) % 3],
+                        triangle.vertices[(i + 2) % 3],
+                        outside,
+                        t,
+                    )),
+                }
+            }
+        }
+
+        for &t in cavity.iter() {
+            self.triangles[t].alive = false;
+        }
+
+        // The cavity is filled with a fan of triangles from the point to its edges
+        let first = self.triangles.len();
+        let mut starting_at: HashMap<usize, usize> = HashMap::new();
+        for (a, b, outside, old) in boundary {
+            let new = self.triangles.len();
+            self.triangles.push(Triangle {
+                vertices: [index, a, b],
+                neighbors: [outside, None, None],
+                alive: true,
+            });
+
+            if let Some(outside) = outside {
+                for neighbor in self.triangles[outside].neighbors.iter_mut() {
+                    if *neighbor == Some(old) {
+                        *neighbor = Some(new);
+                    }
+                }
+            }
+            starting_at.insert(a, new);
+        }
+
+        for new in first..self.triangles.len() {
+            // The edge from `b` to the point is shared with the triangle starting at `b`
+            let b = self.triangles[new].vertices[2];
+            if let Some(&next) = starting_at.get(&b) {
+                self.triangles[new].neighbors[1] = Some(next);
+                self.triangles[next].neighbors[2] = Some(new);
+            }
+        }
+
+        self.last = first;
+    }
+
+    /// Triangle holding `point`, walking towards it from the last inserted triangle.
+    fn locate(&self, point: Vec2) -> Option<usize> {
+        let mut current = self.last;
+        for _ in 0..self.triangles.len() {
+            let triangle = &self.triangles[current];
+            let next = (0..3).find_map(|i| {
+                let a = self.points[triangle.vertices[(i + 1) % 3]];
+                let b = self.points[triangle.vertices[(i + 2) % 3]];
+                if orientation(a, b, point) < 0.0 {
+                    triangle.neighbors[i]
+                } else {
+                    None
+                }
+            });
+
+            match next {
+                Some(next) => current = next,
+                None => return Some(current),
+            }
+        }
+
+        // The walk went round in circles, only possible with rounding errors
+        self.triangles.iter().position(|triangle| {
+            let [a, b, c] = triangle.vertices.map(|v| self.points[v]);
+            triangle.alive
+                && orientation(a, b, point) >= 0.0
+                && orientation(b, c, point) >= 0.0
+                && orientation(c, a, point) >= 0.0
+        })
+    }
+
+    fn in_circumcircle(&self, triangle: usize, point: Vec2) -> bool {
+        let [a, b, c] = self.triangles[triangle]
+            .vertices
+            .map(|v| self.points[v] - point);
+        let det = (a.x * a.x + a.y * a.y) * (b.x * c.y - c.x * b.y)
+            - (b.x * b.x + b.y * b.y) * (a.x * c.y - c.x * a.y)
+            + (c.x * c.x + c.y * c.y) * (a.x * b.y - b.x * a.y);
+        det > 0.0
+    }
+
+    /// `None` for a flat triangle.
+    fn circumcenter(&self, triangle: usize) -> Option<Vec2> {
+        let [a, b, c] = self.triangles[triangle].vertices.map(|v| self.points[v]);
+        let (b, c) = (b - a, c - a);
+        let d = 2.0 * (b.x * c.y - b.y * c.x);
+        if d.abs() < EPSILON {
+            return None;
+        }
+
+        let (b2, c2) = (b.x * b.x + b.y * b.y, c.x * c.x + c.y * c.y);
+        Some(a + Vec2::new((c.y * b2 - b.y * c2) / d, (b.x * c2 - c.x * b2) / d))
+    }
+}
+
+/// Points closer than the square root of `EPSILON` have the same key.
+fn key(point: Vec2) -> (i64, i64) {
+    (
+        (point.x / EPSILON.sqrt()).round() as i64,
+        (point.y / EPSILON.sqrt()).round() as i64,
+    )
+}
+
+/// Positive when `a`, `b`, `c` turn counterclockwise.
+fn orientation(a: Vec2, b: Vec2, c: Vec2) -> f64 {
+    (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x)
+}
@@ -0,0 +1,124 @@
+use std::{fmt::Display, fs, path::Path};
+
+use serde_json::{json, Value};
+
+use crate::milling::{
+    clearing::{ClearingOptions, ClearingPattern},
+    isolation::{IsolationMode, IsolationOptions, MAX_PASSES},
+    toolpath::{MAX_OVERLAP, MIN_TOOL_DIAMETER},
+};
+
+/// Settings saved in a project file, a JSON document. Values missing from a file keep
+/// their default.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Project {
+    pub isolation: IsolationOptions,
+    pub clearing: ClearingOptions,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProjectError {
+    Io(String),
+    Parse(String),
+}
+
+impl Display for ProjectError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProjectError::Io(message) => write!(f, "Cannot access project file: {message}"),
+            ProjectError::Parse(message) => write!(f, "Invalid project file: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for ProjectError {}
+
+impl Project {
+    pub fn read(path: &Path) -> Result<Self, ProjectError> {
+        let content = fs::read_to_string(path).map_err(|e| ProjectError::Io(e.to_string()))?;
+        let json: Value =
+            serde_json::from_str(&content).map_err(|e| ProjectError::Parse(e.to_string()))?;
+
+        let defaults = IsolationOptions::default();
+        let isolation = &json["isolation"];
+        let clearing_defaults = ClearingOptions::default();
+        let clearing = &json["clearing"];
+
+        Ok(Self {
+            isolation: IsolationOptions {
+                mode: match isolation["mode"].as_str() {
+                    Some("voronoi") => IsolationMode::Voronoi,
+                    Some("offset") => IsolationMode::Offset,
+                    _ => defaults.mode,
+                },
+                tool_diameters: match isolation["tool_diameters"].as_array() {
+                    Some(tools) if !tools.is_empty() => {
+                        tools.iter().map(tool_diameter).collect::<Result<_, _>>()?
+                    }
+                    _ => defaults.tool_diameters,
+                },
+                passes: isolation["passes"]
+                    .as_u64()
+                    .map(|passes| passes.clamp(1, MAX_PASSES as u64) as u32)
+                    .unwrap_or(defaults.passes),
+                overlap: isolation["overlap"]
+                    .as_f64()
+                    .map(|overlap| overlap.clamp(0.0, MAX_OVERLAP))
+                    .unwrap_or(defaults.overlap),
+            },
+            clearing: ClearingOptions {
+                pattern: match clearing["pattern"].as_str() {
+                    Some("concentric") => ClearingPattern::Concentric,
+                    Some("zigzag") => ClearingPattern::Zigzag,
+                    _ => clearing_defaults.pattern,
+                },
+                tool_diameter: match &clearing["tool_diameter"] {
+                    Value::Null => clearing_defaults.tool_diameter,
+                    tool => tool_diameter(tool)?,
+                },
+                overlap: clearing["overlap"]
+                    .as_f64()
+                    .map(|overlap| overlap.clamp(0.0, MAX_OVERLAP))
+                    .unwrap_or(clearing_defaults.overlap),
+                margin: clearing["margin"]
+                    .as_f64()
+                    .unwrap_or(clearing_defaults.margin),
+            },
+        })
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), ProjectError> {
+        let json = json!({
+            "isolation": {
+                "mode": match self.isolation.mode {
+                    IsolationMode::Offset => "offset",
+                    IsolationMode::Voronoi => "voronoi",
+                },
+                "tool_diameters": self.isolation.tool_diameters,
+                "passes": self.isolation.passes,
+                "overlap": self.isolation.overlap,
+            },
+            "clearing": {
+                "pattern": match self.clearing.pattern {
+                    ClearingPattern::Concentric => "concentric",
+                    ClearingPattern::Zigzag => "zigzag",
+                },
+                "tool_diameter": self.clearing.tool_diameter,
+                "overlap": self.clearing.overlap,
+                "margin": self.clearing.margin,
+            }
+        });
+
+        let content =
+            serde_json::to_string_pretty(&json).map_err(|e| ProjectError::Parse(e.to_string()))?;
+        fs::write(path, content).map_err(|e| ProjectError::Io(e.to_string()))
+    }
+}
+
+/// A tool diameter the milling can cut with, at least `MIN_TOOL_DIAMETER`.
+fn tool_diameter(value: &Value) -> Result<f64, ProjectError> {
+    value
+        .as_f64()
+        .filter(|diameter| *diameter >= MIN_TOOL_DIAMETER)
+        .ok_or_else(|| ProjectError::Parse(format!("Invalid tool diameter: {value}")))
+}
@@ -4,12 +4,13 @@ use iced::time::{self, Duration};
 use iced::window::{self, Settings};
 use iced::{
     widget::{center, column, container, mouse_area, opaque, row, stack, text},
-    Color, Element, Length, Padding, Subscription,
+    Color, Element, Length, Padding, Subscription, Task,
 };
-use log::info;
+use log::{error, info};
 
 use crate::app_logger::{AppLogger, LogType};
 use crate::base_window::BaseWindow;
+use crate::project::Project;
 use crate::ui::message::{MainWindowAction, MainWindowMessage};
 use crate::ui::widgets::main_window::app_menu_bar::AppMenuBar;
 use crate::ui::widgets::main_window::gerber_canvas::GerberCanvas;
@@ -52,6 +53,39 @@ impl MainWindow {
             }),
         ))
     }
+
+    async fn save_project(project: Project) {
+        info!("Open file explorer");
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("Rusty PCB project", &["rpcb"])
+            .save_file()
+        else {
+            return;
+        };
+
+        match project.write(&path) {
+            Ok(()) => info!("Project saved to {}", path.display()),
+            Err(e) => error!("Cannot save project: {e}"),
+        }
+    }
+
+    async fn load_project() -> Option<Project> {
+        info!("Open file explorer");
+        let path = rfd::FileDialog::new()
+            .add_filter("Rusty PCB project", &["rpcb"])
+            .pick_file()?;
+
+        match Project::read(&path) {
+            Ok(project) => {
+                info!("Project loaded from {}", path.display());
+                Some(project)
+            }
+            Err(e) => {
+                error!("Cannot load project: {e}");
+                None
+            }
+        }
+    }
 }
 
 impl BaseWindow<MainWindowMessage, MainWindowAction> for MainWindow {
@@ -87,8 +121,7 @@ impl BaseWindow<MainWindowMessage, MainWindowAction> for MainWindow {
     fn update(&mut self, message: MainWindowMessage) -> MainWindowAction {
         match message {
             MainWindowMessage::GerberCanvas(gerber_canvas_message) => {
-                self.gerber_canvas.update(gerber_canvas_message);
-                MainWindowAction::None
+                MainWindowAction::Run(self.gerber_canvas.update(gerber_canvas_message))
             }
             MainWindowMessage::TabBar(tab_bar_message) => {
                 MainWindowAction::Run(self.tab_bar.update(tab_bar_message))
@@ -111,6 +144,19 @@ impl BaseWindow<MainWindowMessage, MainWindowAction> for MainWindow {
                 }
                 MainWindowAction::None
             }
+            MainWindowMessage::SaveProject => MainWindowAction::Run(
+                Task::future(Self::save_project(self.tab_bar.project())).discard(),
+            ),
+            MainWindowMessage::LoadProject => MainWindowAction::Run(Task::perform(
+                Self::load_project(),
+                MainWindowMessage::ProjectLoaded,
+            )),
+            MainWindowMessage::ProjectLoaded(project) => {
+                if let Some(project) = project {
+                    self.tab_bar.set_project(project);
+                }
+                MainWindowAction::None
+            }
             MainWindowMessage::ChangeTheme(theme) => MainWindowAction::ChangeTheme(theme),
             MainWindowMessage::OpenToolDB => {
                 if self.db_window_id.is_none() {
@@ -1,14 +1,23 @@
 use std::fmt::Debug;
 
-use iced::{advanced::graphics::core::window, Point, Task};
+use iced::{advanced::graphics::core::window, Color, Point, Task};
 
 use crate::{
-    layer::layer::Layer,
-    ui::widgets::main_window::{tab_bar::TabBarId, tabs::files::TabFileMessage},
+    layer::{layer::Layer, outline::GeneratedOutline},
+    milling::{
+        clearing::ClearingOptions,
+        isolation::IsolationOptions,
+        toolpath::{Operation, Toolpath},
+    },
+    project::Project,
+    ui::widgets::main_window::{
+        tab_bar::TabBarId,
+        tabs::{drilling::TabDrillingMessage, files::TabFileMessage, milling::TabMillingMessage},
+    },
     AppTheme,
 };
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum CanvasLayer {
     Top,
     Bottom,
@@ -16,6 +25,17 @@ pub enum CanvasLayer {
     Outline,
 }
 
+impl CanvasLayer {
+    pub fn color(&self) -> Color {
+        match self {
+            CanvasLayer::Top => Color::from_rgb(0.0, 0.0, 1.0),
+            CanvasLayer::Bottom => Color::from_rgb(1.0, 0.0, 0.0),
+            CanvasLayer::Drill => Color::from_rgb(1.0, 0.5, 0.0),
+            CanvasLayer::Outline => Color::from_rgb(0.0, 1.0, 0.0),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum AppMessage {
     MainWindow(MainWindowMessage),
@@ -31,6 +51,10 @@ pub enum MainWindowMessage {
 
     OpenToolDB,
 
+    SaveProject,
+    LoadProject,
+    ProjectLoaded(Option<Project>),
+
     GerberCanvas(GerberCanvasMessage),
     TabBar(TabBarMessage),
 }
@@ -61,11 +85,16 @@ pub enum GerberCanvasMessage {
     ZoomOut,
 
     LoadLayer(CanvasLayer, Layer),
+    GenerateOutline(GeneratedOutline),
+    GenerateIsolation(IsolationOptions),
+    GenerateClearing(ClearingOptions),
+    ToolpathsGenerated(Operation, Vec<Toolpath>, Vec<Toolpath>),
 
     ShowTopLayer(bool),
     ShowBotLayer(bool),
     ShowDrillLayer(bool),
     ShowOutlineLayer(bool),
+    ShowToolpaths(bool),
 
     ClearTopLayer,
     ClearBottomLayer,
@@ -77,4 +106,7 @@ pub enum GerberCanvasMessage {
 pub enum TabBarMessage {
     TabSelected(TabBarId),
     TabFileMessage(TabFileMessage),
+    Milling(TabMillingMessage),
+    Drilling(TabDrillingMessage),
+    BoardThickness(f64),
 }
@@ -1,7 +1,7 @@
 use std::path::PathBuf;
 
 use iced::{
-    widget::{button, container, row, text, text_input, tooltip},
+    widget::{button, column, container, row, text, text_input, tooltip},
     Length, Task,
 };
 use log::{info, warn};
@@ -12,37 +12,37 @@ static mut LAST_FOLDER: Option<PathBuf> = None;
 pub enum FilePickerMessage {
     BrowseFile,
     ClearFile,
-    OnFileDialogClose(Option<PathBuf>),
+    OnFileDialogClose(Option<Vec<PathBuf>>),
 }
 
 pub enum FilePickerAction {
     Run(Task<FilePickerMessage>),
-    FileSelected(PathBuf),
+    FileSelected(Vec<PathBuf>),
     ClearFile,
     None,
 }
 
 #[derive(Debug)]
 pub struct FilePicker {
-    file: PathBuf,
+    files: Vec<PathBuf>,
     is_enable: bool,
+    is_multiple: bool,
     filter_name: String,
     filter_pattern: Vec<String>,
     is_open: bool,
+    error: Option<String>,
 }
 
 impl FilePicker {
     pub fn new(file: Option<PathBuf>, filter_name: String, filter_pattern: Vec<String>) -> Self {
         Self {
-            file: if let Some(file) = file {
-                file
-            } else {
-                PathBuf::default()
-            },
+            files: file.into_iter().collect(),
             is_enable: true,
+            is_multiple: false,
             filter_name,
             filter_pattern,
             is_open: false,
+            error: None,
         }
     }
 
@@ -52,7 +52,11 @@ impl FilePicker {
                 if self.is_open == false {
                     self.is_open = true;
                     FilePickerAction::Run(Task::perform(
-                        Self::pick_file(self.filter_name.clone(), self.filter_pattern.clone()),
+                        Self::pick_file(
+                            self.filter_name.clone(),
+                            self.filter_pattern.clone(),
+                            self.is_multiple,
+                        ),
                         move |x| FilePickerMessage::OnFileDialogClose(x),
                     ))
                 } else {
@@ -61,19 +65,21 @@ impl FilePicker {
                 }
             }
             FilePickerMessage::ClearFile => {
-                self.file = PathBuf::new();
+                self.files.clear();
+                self.error = None;
                 FilePickerAction::ClearFile
             }
             FilePickerMessage::OnFileDialogClose(path_buf) => {
                 self.is_open = false;
                 match path_buf {
-                    Some(path_buf) => {
-                        self.file = path_buf.clone();
-                        Self::set_last_path(path_buf.clone());
+                    Some(path_buf) if !path_buf.is_empty() => {
+                        self.files = path_buf.clone();
+                        self.error = None;
+                        Self::set_last_path(path_buf[0].clone());
 
                         FilePickerAction::FileSelected(path_buf)
                     }
-                    None => FilePickerAction::None,
+                    _ => FilePickerAction::None,
                 }
             }
         }
@@ -89,8 +95,8 @@ impl FilePicker {
             btn_browse = btn_browse.on_press(FilePickerMessage::BrowseFile);
         }
 
-        row![
-            text_input("", self.file_to_string()).width(Length::Fill),
+        let picker = row![
+            text_input("", &self.files_to_string()).width(Length::Fill),
             btn_browse,
             tooltip(
                 button(text("🗑️").shaping(text::Shaping::Advanced))
@@ -103,19 +109,49 @@ impl FilePicker {
                 tooltip::Position::Bottom,
             )
         ]
-        .spacing(4)
-        .into()
+        .spacing(4);
+
+        match &self.error {
+            Some(error) => column![picker, text(error.clone()).style(text::danger)]
+                .spacing(2)
+                .into(),
+            None => picker.into(),
+        }
     }
 
     pub fn enable(&mut self, is_enable: bool) {
         self.is_enable = is_enable;
     }
 
-    fn file_to_string(&self) -> &str {
-        self.file.to_str().unwrap_or("")
+    /// Let the dialog select several files at once.
+    pub fn multiple(&mut self, is_multiple: bool) {
+        self.is_multiple = is_multiple;
     }
 
-    async fn pick_file(filter_name: String, filter_pattern: Vec<String>) -> Option<PathBuf> {
+    /// Select files chosen somewhere else than in the dialog.
+    pub fn set_files(&mut self, files: Vec<PathBuf>) {
+        self.files = files;
+        self.error = None;
+    }
+
+    /// Show why the selected file could not be used.
+    pub fn set_error(&mut self, error: Option<String>) {
+        self.error = error;
+    }
+
+    fn files_to_string(&self) -> String {
+        self.files
+            .iter()
+            .map(|file| file.to_str().unwrap_or(""))
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+
+    async fn pick_file(
+        filter_name: String,
+        filter_pattern: Vec<String>,
+        is_multiple: bool,
+    ) -> Option<Vec<PathBuf>> {
         info!("Open file explorer");
         let mut dialog = rfd::FileDialog::new()
             .add_filter(filter_name, &filter_pattern)
@@ -125,7 +161,11 @@ impl FilePicker {
             dialog = dialog.set_directory(path);
         }
 
-        dialog.pick_file()
+        if is_multiple {
+            dialog.pick_files()
+        } else {
+            dialog.pick_file().map(|file| vec![file])
+        }
     }
 
     fn set_last_path(path: PathBuf) {
@@ -27,8 +27,8 @@ impl AppMenuBar {
         let menu_bar = menu_bar!(
             (Self::menu_button("File"),
             menu_template(menu_items!(
-                (Self::item_button("Save project"))
-                (Self::item_button("Load project"))
+                (Self::item_button("Save project").on_press(MainWindowMessage::SaveProject))
+                (Self::item_button("Load project").on_press(MainWindowMessage::LoadProject))
             )))
             (Self::menu_button("View"),
             menu_template(menu_items!(
@@ -7,11 +7,18 @@ use iced::{
         canvas::{self, stroke, Cache, Fill, Frame, Path, Stroke},
         checkbox, column, row, Canvas, Column,
     },
-    Color, Length, Point, Rectangle, Renderer, Theme, Vector,
+    Color, Length, Point, Rectangle, Renderer, Task, Theme, Vector,
 };
 
+use log::{error, info, warn};
+
 use crate::{
-    layer::layer::Layer,
+    layer::{layer::Layer, vec2::Vec2},
+    milling::{
+        clearing::clearing,
+        isolation::isolation,
+        toolpath::{Operation, Toolpath},
+    },
     ui::message::{CanvasLayer, GerberCanvasMessage, MainWindowMessage},
 };
 
@@ -19,6 +26,7 @@ const STEP_ZOOM_MENU: f32 = 0.5;
 const MAX_SCALE: f32 = 100.0;
 const MIN_SCALE: f32 = 0.01;
 const ZOOM_OPTIMAL_MARGIN: f32 = 5.0;
+const TOOLPATH_COLOR: Color = Color::from_rgb(0.0, 1.0, 1.0);
 
 #[derive(Debug, Default)]
 pub struct GerberCanvasInternalState {
@@ -39,12 +47,17 @@ pub struct GerberCanvas {
     drill_layer: Layer,
     outline_layer: Layer,
 
+    /// Cutting order, operation after operation
+    top_toolpaths: Vec<Toolpath>,
+    bot_toolpaths: Vec<Toolpath>,
+
     canvas_size: RefCell<iced::Rectangle>,
 
     show_top_layer: bool,
     show_bot_layer: bool,
     show_drill_layer: bool,
     show_outline_layer: bool,
+    show_toolpaths: bool,
 }
 
 impl GerberCanvas {
@@ -68,6 +81,10 @@ impl GerberCanvas {
                         MainWindowMessage::GerberCanvas(GerberCanvasMessage::ShowOutlineLayer(x))
                     })
                 ]
+                .spacing(5),
+                column![checkbox("Toolpaths", self.show_toolpaths).on_toggle(|x| {
+                    MainWindowMessage::GerberCanvas(GerberCanvasMessage::ShowToolpaths(x))
+                })]
                 .spacing(5)
             ]
             .spacing(20)
@@ -77,7 +94,7 @@ impl GerberCanvas {
         .spacing(5)
     }
 
-    pub fn update(&mut self, message: GerberCanvasMessage) {
+    pub fn update(&mut self, message: GerberCanvasMessage) -> Task<MainWindowMessage> {
         match message {
             GerberCanvasMessage::ZoomIn => {
                 let px = self.canvas_size.borrow().width / 2.0;
@@ -106,14 +123,18 @@ impl GerberCanvas {
                 self.force_redraw();
             }
             GerberCanvasMessage::LoadLayer(canvas_layer, layer) => {
+                self.warn_format_mismatch(&canvas_layer, &layer);
+                // Toolpaths of the replaced copper or outline no longer match the board
                 match canvas_layer {
                     CanvasLayer::Top => {
                         self.show_top_layer = true;
                         self.top_layer = layer;
+                        self.top_toolpaths.clear();
                     }
                     CanvasLayer::Bottom => {
                         self.show_bot_layer = true;
                         self.bot_layer = layer;
+                        self.bot_toolpaths.clear();
                     }
                     CanvasLayer::Drill => {
                         self.show_drill_layer = true;
@@ -122,11 +143,58 @@ impl GerberCanvas {
                     CanvasLayer::Outline => {
                         self.show_outline_layer = true;
                         self.outline_layer = layer;
+                        self.clear_toolpaths();
                     }
                 };
                 self.reset_view();
                 self.force_redraw();
             }
+            GerberCanvasMessage::GenerateOutline(outline) => {
+                match Layer::from_copper(
+                    &[&self.top_layer, &self.bot_layer],
+                    CanvasLayer::Outline.color(),
+                    &outline,
+                ) {
+                    Ok(layer) => {
+                        info!("Outline generated around the copper");
+                        self.show_outline_layer = true;
+                        self.outline_layer = layer;
+                        self.clear_toolpaths();
+                        self.force_redraw();
+                    }
+                    Err(e) => error!("Cannot generate the outline: {e}"),
+                }
+            }
+            GerberCanvasMessage::GenerateIsolation(options) => {
+                return self.generate_task(Operation::Isolation, move |top, bot, board| {
+                    let top = isolation(&top, &board, &options);
+                    let bot = isolation(&bot, &board, &options);
+
+                    if top.is_empty() && bot.is_empty() {
+                        error!("No copper layer to isolate");
+                    }
+                    (top, bot)
+                });
+            }
+            GerberCanvasMessage::GenerateClearing(options) => {
+                if self.outline_layer.empty() {
+                    error!("No board outline to clear, load or generate one");
+                    return Task::none();
+                }
+
+                return self.generate_task(Operation::Clearing, move |top, bot, board| {
+                    let top = clearing(&top, &board, &options);
+                    let bot = clearing(&bot, &board, &options);
+
+                    if top.empty() && bot.empty() {
+                        error!("No copper to clear on the board");
+                    }
+                    (vec![top], vec![bot])
+                });
+            }
+            GerberCanvasMessage::ToolpathsGenerated(operation, top, bot) => {
+                self.set_toolpaths(operation, top, bot)
+            }
             GerberCanvasMessage::ShowTopLayer(is_show) => {
                 if self.top_layer.empty() {
                     self.show_top_layer = false;
@@ -159,11 +227,47 @@ impl GerberCanvas {
                     self.force_redraw();
                 }
             }
+            GerberCanvasMessage::ShowToolpaths(is_show) => {
+                if self.top_toolpaths.is_empty() && self.bot_toolpaths.is_empty() {
+                    self.show_toolpaths = false;
+                } else {
+                    self.show_toolpaths = is_show;
+                    self.force_redraw();
+                }
+            }
             GerberCanvasMessage::ClearTopLayer => self.clear_top_layer(),
             GerberCanvasMessage::ClearBottomLayer => self.clear_bottom_layer(),
             GerberCanvasMessage::ClearDrillLayer => self.clear_drill_layer(),
             GerberCanvasMessage::ClearOutlineLayer => self.clear_outline_layer(),
         };
+
+        Task::none()
+    }
+
+    /// Toolpaths of `operation` computed away from the UI thread, from copies of the top
+    /// and bottom copper and of the outline.
+    fn generate_task(
+        &self,
+        operation: Operation,
+        generate: impl FnOnce(Layer, Layer, Layer) -> (Vec<Toolpath>, Vec<Toolpath>)
+            + Send
+            + 'static,
+    ) -> Task<MainWindowMessage> {
+        let (top, bot, board) = (
+            self.top_layer.clone(),
+            self.bot_layer.clone(),
+            self.outline_layer.clone(),
+        );
+
+        Task::done(MainWindowMessage::ShowLoading)
+            .chain(
+                Task::future(async move { generate(top, bot, board) }).map(move |(top, bot)| {
+                    MainWindowMessage::GerberCanvas(GerberCanvasMessage::ToolpathsGenerated(
+                        operation, top, bot,
+                    ))
+                }),
+            )
+            .chain(Task::done(MainWindowMessage::HideLoading))
     }
 
     pub fn force_redraw(&mut self) {
@@ -202,13 +306,73 @@ impl GerberCanvas {
         self.force_redraw();
     }
 
+    /// Replace the toolpaths of `operation` on each side, the operations staying in
+    /// cutting order.
+    fn set_toolpaths(&mut self, operation: Operation, top: Vec<Toolpath>, bot: Vec<Toolpath>) {
+        for (side, toolpaths, new) in [
+            ("Top", &mut self.top_toolpaths, top),
+            ("Bottom", &mut self.bot_toolpaths, bot),
+        ] {
+            toolpaths.retain(|toolpath| toolpath.operation != operation);
+
+            for toolpath in new.into_iter().filter(|toolpath| !toolpath.empty()) {
+                info!(
+                    "{side} {operation} with a {:.2} mm tool: {} cuts, {:.1} mm",
+                    toolpath.tool_diameter,
+                    toolpath.cuts().len(),
+                    toolpath.length()
+                );
+                toolpaths.push(toolpath);
+                self.show_toolpaths = true;
+            }
+            toolpaths.sort_by_key(|toolpath| toolpath.operation);
+        }
+
+        self.force_redraw();
+    }
+
+    /// Files of one project are expected to share the same unit and coordinate format.
+    fn warn_format_mismatch(&self, canvas_layer: &CanvasLayer, layer: &Layer) {
+        // Drill files have no Gerber format, unknown values are not compared
+        fn differs<T: PartialEq>(a: Option<T>, b: Option<T>) -> bool {
+            a.is_some() && b.is_some() && a != b
+        }
+
+        let others = [
+            (CanvasLayer::Top, &self.top_layer),
+            (CanvasLayer::Bottom, &self.bot_layer),
+            (CanvasLayer::Drill, &self.drill_layer),
+            (CanvasLayer::Outline, &self.outline_layer),
+        ];
+
+        for (other_layer, other) in others {
+            if other_layer == *canvas_layer || other.empty() {
+                continue;
+            }
+
+            if differs(other.source_unit(), layer.source_unit())
+                || differs(other.format(), layer.format())
+            {
+                warn!(
+                    "{:?} file format ({}) differs from {:?} file format ({})",
+                    canvas_layer,
+                    layer.format_description(),
+                    other_layer,
+                    other.format_description()
+                );
+            }
+        }
+    }
+
     pub fn clear_top_layer(&mut self) {
         self.top_layer.clear();
+        self.top_toolpaths.clear();
         self.force_redraw();
     }
 
     pub fn clear_bottom_layer(&mut self) {
         self.bot_layer.clear();
+        self.bot_toolpaths.clear();
         self.force_redraw();
     }
 
@@ -219,9 +383,35 @@ impl GerberCanvas {
 
     pub fn clear_outline_layer(&mut self) {
         self.outline_layer.clear();
+        self.clear_toolpaths();
         self.force_redraw();
     }
 
+    /// Toolpaths of both sides, cut inside an outline that changed.
+    fn clear_toolpaths(&mut self) {
+        self.top_toolpaths.clear();
+        self.bot_toolpaths.clear();
+    }
+
+    /// Cursor position and attributes of the copper object under it, top layer first.
+    fn hovered_object(
+        &self,
+        bounds: Rectangle,
+        cursor: iced::advanced::mouse::Cursor,
+    ) -> Option<(Point, String)> {
+        let position = cursor.position_in(bounds)?;
+        let point = Vec2::new(
+            ((position.x - self.translate.x) / self.scale) as f64,
+            (-(position.y - self.translate.y) / self.scale) as f64,
+        );
+
+        [(self.show_top_layer, &self.top_layer), (self.show_bot_layer, &self.bot_layer)]
+            .into_iter()
+            .filter(|(is_show, _)| *is_show)
+            .find_map(|(_, layer)| layer.object_at(point))
+            .map(|attributes| (position, attributes.description()))
+    }
+
     fn show_axis(&self, bounds: &Rectangle, frame: &mut Frame) {
         frame.stroke(
             &Path::new(|b| {
@@ -261,11 +451,14 @@ impl Default for GerberCanvas {
             top_layer: Default::default(),
             drill_layer: Default::default(),
             outline_layer: Default::default(),
+            top_toolpaths: Default::default(),
+            bot_toolpaths: Default::default(),
             canvas_size: RefCell::new(Rectangle::default()),
             show_top_layer: false,
             show_bot_layer: false,
             show_drill_layer: false,
             show_outline_layer: false,
+            show_toolpaths: false,
         }
     }
 }
@@ -279,7 +472,7 @@ impl canvas::Program<MainWindowMessage> for GerberCanvas {
         renderer: &Renderer,
         _theme: &Theme,
         bounds: iced::Rectangle,
-        _cursor: iced::advanced::mouse::Cursor,
+        cursor: iced::advanced::mouse::Cursor,
     ) -> Vec<canvas::Geometry<Renderer>> {
         let geometry = self.cache.draw(renderer, bounds.size(), |frame| {
             self.canvas_size.replace(bounds);
@@ -348,10 +541,38 @@ impl canvas::Program<MainWindowMessage> for GerberCanvas {
                 self.outline_layer.draw(frame);
             }
 
+            if self.show_toolpaths {
+                if self.show_top_layer {
+                    for toolpath in self.top_toolpaths.iter() {
+                        toolpath.draw(frame, TOOLPATH_COLOR);
+                    }
+                }
+
+                if self.show_bot_layer {
+                    for toolpath in self.bot_toolpaths.iter() {
+                        toolpath.draw(frame, TOOLPATH_COLOR);
+                    }
+                }
+            }
+
             // ----- END DRAW CIRCUIT -----
         });
 
-        vec![geometry]
+        let mut geometries = vec![geometry];
+
+        // Not cached, it follows the cursor
+        if let Some((position, description)) = self.hovered_object(bounds, cursor) {
+            let mut frame = Frame::new(renderer, bounds.size());
+            frame.fill_text(canvas::Text {
+                content: description,
+                position: position + Vector::new(12.0, 12.0),
+                color: Color::WHITE,
+                ..Default::default()
+            });
+            geometries.push(frame.into_geometry());
+        }
+
+        geometries
     }
 
     fn update(
@@ -1,9 +1,12 @@
 use iced::Task;
 use iced_aw::Tabs;
 
-use crate::ui::{
-    message::{MainWindowMessage, TabBarMessage},
-    widgets::main_window::tabs::{drilling::Drilling, files::Files, milling::Milling},
+use crate::{
+    project::Project,
+    ui::{
+        message::{MainWindowMessage, TabBarMessage},
+        widgets::main_window::tabs::{drilling::Drilling, files::Files, milling::Milling},
+    },
 };
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -29,9 +32,31 @@ impl TabBar {
                 Task::none()
             }
             TabBarMessage::TabFileMessage(tab_file_message) => self.files.update(tab_file_message),
+            TabBarMessage::Milling(tab_milling_message) => self.milling.update(tab_milling_message),
+            TabBarMessage::Drilling(tab_drilling_message) => {
+                self.drilling.update(tab_drilling_message)
+            }
+            TabBarMessage::BoardThickness(thickness) => {
+                self.drilling.set_board_thickness(thickness);
+                self.milling.set_board_thickness(thickness);
+                Task::none()
+            }
+        }
+    }
+
+    /// Settings of the tabs saved with the project.
+    pub fn project(&self) -> Project {
+        Project {
+            isolation: self.milling.isolation(),
+            clearing: self.milling.clearing(),
         }
     }
 
+    pub fn set_project(&mut self, project: Project) {
+        self.milling.set_isolation(project.isolation);
+        self.milling.set_clearing(project.clearing);
+    }
+
     pub fn view(&self) -> Tabs<MainWindowMessage, TabBarId> {
         Tabs::new(|id| MainWindowMessage::TabBar(TabBarMessage::TabSelected(id)))
             .push(TabBarId::Files, self.files.tab_label(), self.files.view())
@@ -1,17 +1,137 @@
-use iced::{widget::text, Element};
+use iced::{
+    padding,
+    widget::{column, radio, row, text},
+    Element, Task,
+};
 use iced_aw::TabLabel;
 
-use crate::ui::message::MainWindowMessage;
+use crate::{
+    drill::drill_file::{DrillFile, DrillSet, SlotStrategy},
+    ui::message::{MainWindowMessage, TabBarMessage},
+};
 
-#[derive(Debug, Default)]
-pub struct Drilling {}
+#[derive(Debug, Clone)]
+pub enum TabDrillingMessage {
+    DrillFileLoaded(Option<DrillFile>),
+    DrillSetChange(DrillSet),
+    SlotStrategyChange(SlotStrategy),
+}
+
+#[derive(Debug)]
+pub struct Drilling {
+    drill: Option<DrillFile>,
+    drill_set: DrillSet,
+    slot_strategy: SlotStrategy,
+    board_thickness: Option<f64>,
+    /// Holes and slots to machine, computed when the file or the settings change.
+    summary: String,
+}
+
+impl Default for Drilling {
+    fn default() -> Self {
+        let mut drilling = Self {
+            drill: None,
+            drill_set: DrillSet::default(),
+            slot_strategy: SlotStrategy::default(),
+            board_thickness: None,
+            summary: String::new(),
+        };
+        drilling.summary = drilling.summary();
+        drilling
+    }
+}
 
 impl Drilling {
     pub fn tab_label(&self) -> TabLabel {
         TabLabel::Text("Drilling".to_string())
     }
 
+    pub fn update(&mut self, message: TabDrillingMessage) -> Task<MainWindowMessage> {
+        match message {
+            TabDrillingMessage::DrillFileLoaded(drill) => self.drill = drill,
+            TabDrillingMessage::DrillSetChange(drill_set) => self.drill_set = drill_set,
+            TabDrillingMessage::SlotStrategyChange(slot_strategy) => {
+                self.slot_strategy = slot_strategy
+            }
+        }
+        self.summary = self.summary();
+
+        Task::none()
+    }
+
+    /// Drill depth default, going through the board.
+    pub fn set_board_thickness(&mut self, thickness: f64) {
+        self.board_thickness = Some(thickness);
+    }
+
     pub fn view(&self) -> Element<MainWindowMessage> {
-        text("Drilling panel").into()
+        let on_set_change = |set| {
+            MainWindowMessage::TabBar(TabBarMessage::Drilling(TabDrillingMessage::DrillSetChange(
+                set,
+            )))
+        };
+        let on_change = |strategy| {
+            MainWindowMessage::TabBar(TabBarMessage::Drilling(
+                TabDrillingMessage::SlotStrategyChange(strategy),
+            ))
+        };
+
+        column![
+            "Holes",
+            row![
+                radio("All", DrillSet::Both, Some(self.drill_set), on_set_change),
+                radio(
+                    "Plated",
+                    DrillSet::Plated,
+                    Some(self.drill_set),
+                    on_set_change
+                ),
+                radio(
+                    "Non plated",
+                    DrillSet::NonPlated,
+                    Some(self.drill_set),
+                    on_set_change
+                )
+            ]
+            .spacing(10)
+            .padding(padding::left(20)),
+            "Slots",
+            row![
+                radio(
+                    "Milled",
+                    SlotStrategy::Mill,
+                    Some(self.slot_strategy),
+                    on_change
+                ),
+                radio(
+                    "Row of drills",
+                    SlotStrategy::Drill,
+                    Some(self.slot_strategy),
+                    on_change
+                )
+            ]
+            .spacing(10)
+            .padding(padding::left(20)),
+            text(&self.summary),
+            text(match self.board_thickness {
+                Some(thickness) => format!("Drill depth: {thickness:.2} mm"),
+                None => "Drill depth: unknown board thickness".to_string(),
+            }),
+        ]
+        .spacing(5)
+        .into()
+    }
+
+    fn summary(&self) -> String {
+        let Some(drill) = &self.drill else {
+            return "No drill file".to_string();
+        };
+
+        let drill = drill.for_machining(self.drill_set, self.slot_strategy);
+        format!(
+            "{} holes, {} milled slots",
+            drill.hole_count(),
+            drill.slot_count()
+        )
     }
 }
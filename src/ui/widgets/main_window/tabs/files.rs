@@ -1,20 +1,37 @@
-use std::{fs::File, io::BufReader, path::PathBuf};
+use std::{
+    fmt::Display,
+    fs,
+    io::{BufReader, Read},
+    path::{Path, PathBuf},
+};
 
-use gerber_parser::parser::parse_gerber;
+use gerber_parser::{GerberDoc, ParseError};
+use gerber_types::FilePolarity;
 use iced::{
     padding,
-    widget::{column, horizontal_rule, radio, row, vertical_space},
-    Color, Element, Task,
+    widget::{button, column, horizontal_rule, pick_list, radio, row, text, vertical_space},
+    Element, Length, Task,
 };
-use iced_aw::TabLabel;
+use iced_aw::{helpers::number_input, TabLabel};
 use log::{error, info, warn};
 
 use crate::{
-    layer::layer::Layer,
+    drill::{drill_file::DrillFile, excellon::parse_excellon, gerber_drill::drill_from_gerber},
+    layer::{
+        archive::{classify_archive, open_source},
+        classify::classify_file,
+        error::LayerError,
+        job::{read_job, GerberJob},
+        layer::{Layer, LayerOptions},
+        outline::{GeneratedOutline, OutlineShape},
+    },
     ui::{
         main_window::PcbSides,
         message::{CanvasLayer, GerberCanvasMessage, MainWindowMessage, TabBarMessage},
-        widgets::file_picker::{FilePicker, FilePickerAction, FilePickerMessage},
+        widgets::{
+            file_picker::{FilePicker, FilePickerAction, FilePickerMessage},
+            main_window::tabs::drilling::TabDrillingMessage,
+        },
     },
 };
 
@@ -22,12 +39,54 @@ use crate::{
 pub enum TabFileMessage {
     PcbTypeChange(PcbSides),
     FilePickerMessage(CanvasLayer, FilePickerMessage),
+    LoadFailed(CanvasLayer, LayerError),
+    OpenFolder,
+    OpenArchive,
+    OpenJob,
+    JobRead(Option<GerberJob>),
+    FolderScanned(Vec<(PathBuf, Assignment)>),
+    AssignmentChange(usize, Assignment),
+    LoadAssignments,
+    CancelAssignments,
+    OutlineShapeChange(OutlineShape),
+    OutlineMarginChange(f64),
+    OutlineRadiusChange(f64),
+    GenerateOutline,
+}
+
+/// Layer chosen for a file of an opened folder, `None` to leave the file out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assignment(Option<CanvasLayer>);
+
+impl Assignment {
+    const ALL: &'static [Assignment] = &[
+        Assignment(Some(CanvasLayer::Top)),
+        Assignment(Some(CanvasLayer::Bottom)),
+        Assignment(Some(CanvasLayer::Drill)),
+        Assignment(Some(CanvasLayer::Outline)),
+        Assignment(None),
+    ];
+}
+
+impl Display for Assignment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.0 {
+            Some(canvas_layer) => write!(f, "{canvas_layer:?}"),
+            None => write!(f, "Ignored"),
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct Files {
     pcb_sides: PcbSides,
 
+    /// Files of an opened folder waiting for confirmation
+    assignments: Vec<(PathBuf, Assignment)>,
+
+    /// Outline drawn around the copper when there is no outline file
+    generated_outline: GeneratedOutline,
+
     top_file_picker: FilePicker,
     bot_file_picker: FilePicker,
     drill_file_picker: FilePicker,
@@ -50,13 +109,102 @@ impl Files {
                 Task::none()
             }
 
+            TabFileMessage::OpenFolder => Task::perform(Self::scan_folder(), |files| {
+                MainWindowMessage::TabBar(TabBarMessage::TabFileMessage(
+                    TabFileMessage::FolderScanned(files),
+                ))
+            }),
+
+            TabFileMessage::OpenArchive => Task::perform(Self::scan_archive(), |files| {
+                MainWindowMessage::TabBar(TabBarMessage::TabFileMessage(
+                    TabFileMessage::FolderScanned(files),
+                ))
+            }),
+
+            TabFileMessage::FolderScanned(assignments) => {
+                self.assignments = assignments;
+                Task::none()
+            }
+
+            TabFileMessage::AssignmentChange(index, assignment) => {
+                if let Some((_, current)) = self.assignments.get_mut(index) {
+                    *current = assignment;
+                }
+                Task::none()
+            }
+
+            TabFileMessage::LoadAssignments => self.load_assignments(),
+
+            TabFileMessage::OpenJob => Task::perform(Self::pick_job(), |job| {
+                MainWindowMessage::TabBar(TabBarMessage::TabFileMessage(TabFileMessage::JobRead(
+                    job,
+                )))
+            }),
+
+            TabFileMessage::JobRead(None) => Task::none(),
+
+            TabFileMessage::JobRead(Some(job)) => {
+                if let Some(copper_layers) = job.copper_layers {
+                    self.pcb_sides = if copper_layers >= 2 {
+                        PcbSides::TwoSide
+                    } else {
+                        PcbSides::OneSide
+                    };
+                    self.top_file_picker
+                        .enable(self.pcb_sides == PcbSides::TwoSide);
+                }
+
+                self.assignments = job
+                    .files
+                    .into_iter()
+                    .map(|(file, canvas_layer)| (file, Assignment(Some(canvas_layer))))
+                    .collect();
+
+                match job.board_thickness {
+                    Some(thickness) => Task::done(MainWindowMessage::TabBar(
+                        TabBarMessage::BoardThickness(thickness),
+                    ))
+                    .chain(self.load_assignments()),
+                    None => self.load_assignments(),
+                }
+            }
+
+            TabFileMessage::CancelAssignments => {
+                self.assignments.clear();
+                Task::none()
+            }
+
+            TabFileMessage::OutlineShapeChange(shape) => {
+                self.generated_outline.shape = shape;
+                Task::none()
+            }
+
+            TabFileMessage::OutlineMarginChange(margin) => {
+                self.generated_outline.margin = margin;
+                Task::none()
+            }
+
+            TabFileMessage::OutlineRadiusChange(radius) => {
+                self.generated_outline.corner_radius = radius;
+                Task::none()
+            }
+
+            TabFileMessage::GenerateOutline => {
+                self.outline_file_picker.set_files(Vec::new());
+                Task::done(MainWindowMessage::GerberCanvas(
+                    GerberCanvasMessage::GenerateOutline(self.generated_outline),
+                ))
+            }
+
+            TabFileMessage::LoadFailed(canvas_layer, layer_error) => {
+                self.picker(&canvas_layer)
+                    .set_error(Some(layer_error.to_string()));
+
+                Task::none()
+            }
+
             TabFileMessage::FilePickerMessage(canvas_layer, file_picker_message) => {
-                let picker: &mut FilePicker = match canvas_layer {
-                    CanvasLayer::Top => &mut self.top_file_picker,
-                    CanvasLayer::Bottom => &mut self.bot_file_picker,
-                    CanvasLayer::Drill => &mut self.drill_file_picker,
-                    CanvasLayer::Outline => &mut self.outline_file_picker,
-                };
+                let picker: &mut FilePicker = self.picker(&canvas_layer);
 
                 match picker.update(file_picker_message) {
                     FilePickerAction::Run(task) => task.map(move |x| {
@@ -66,30 +214,7 @@ impl Files {
                     }),
                     FilePickerAction::None => Task::none(),
                     FilePickerAction::FileSelected(path_buf) => {
-                        Task::done(MainWindowMessage::ShowLoading)
-                            .chain(
-                                Task::future(Self::load_file(path_buf, canvas_layer.clone())).then(
-                                    move |x| match x {
-                                        Some(new_layer) => {
-                                            info!(
-                                                "Load new file to {:?} layer",
-                                                canvas_layer.clone()
-                                            );
-                                            Task::done(MainWindowMessage::GerberCanvas(
-                                                GerberCanvasMessage::LoadLayer(
-                                                    canvas_layer.clone(),
-                                                    new_layer,
-                                                ),
-                                            ))
-                                        }
-                                        None => {
-                                            error!("Failed to load file");
-                                            Task::none()
-                                        }
-                                    },
-                                ),
-                            )
-                            .chain(Task::done(MainWindowMessage::HideLoading))
+                        Self::load_task(path_buf, canvas_layer)
                     }
                     FilePickerAction::ClearFile => {
                         warn!("Clear {:?} layer", canvas_layer);
@@ -102,7 +227,8 @@ impl Files {
                             )),
                             CanvasLayer::Drill => Task::done(MainWindowMessage::GerberCanvas(
                                 GerberCanvasMessage::ClearDrillLayer,
-                            )),
+                            ))
+                            .chain(Self::drill_file_loaded(None)),
                             CanvasLayer::Outline => Task::done(MainWindowMessage::GerberCanvas(
                                 GerberCanvasMessage::ClearOutlineLayer,
                             )),
@@ -114,7 +240,7 @@ impl Files {
     }
 
     pub fn view(&self) -> Element<MainWindowMessage> {
-        column![
+        let mut content = column![
             "PCB type",
             row![
                 radio("1-side", PcbSides::OneSide, Some(self.pcb_sides), |id| {
@@ -130,6 +256,18 @@ impl Files {
             ]
             .spacing(10)
             .padding(padding::left(20)),
+            row![
+                button("Open folder").on_press(MainWindowMessage::TabBar(
+                    TabBarMessage::TabFileMessage(TabFileMessage::OpenFolder)
+                )),
+                button("Open zip").on_press(MainWindowMessage::TabBar(
+                    TabBarMessage::TabFileMessage(TabFileMessage::OpenArchive)
+                )),
+                button("Open job").on_press(MainWindowMessage::TabBar(
+                    TabBarMessage::TabFileMessage(TabFileMessage::OpenJob)
+                ))
+            ]
+            .spacing(10),
             horizontal_rule(3),
             "Top file",
             self.top_file_picker
@@ -166,31 +304,349 @@ impl Files {
                         TabFileMessage::FilePickerMessage(CanvasLayer::Outline, x)
                     ))
                 ),
+            self.outline_generator(),
+        ]
+        .spacing(5);
+
+        if !self.assignments.is_empty() {
+            content = content.push(horizontal_rule(3)).push("Files to load");
+
+            for (index, (file, assignment)) in self.assignments.iter().enumerate() {
+                let name = file
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .unwrap_or("")
+                    .to_string();
+
+                content = content.push(
+                    row![
+                        text(name).width(Length::Fill),
+                        pick_list(Assignment::ALL, Some(assignment.clone()), move |x| {
+                            MainWindowMessage::TabBar(TabBarMessage::TabFileMessage(
+                                TabFileMessage::AssignmentChange(index, x),
+                            ))
+                        })
+                    ]
+                    .spacing(5)
+                    .padding(padding::left(20)),
+                );
+            }
+
+            content = content.push(
+                row![
+                    button("Load").on_press(MainWindowMessage::TabBar(
+                        TabBarMessage::TabFileMessage(TabFileMessage::LoadAssignments)
+                    )),
+                    button("Cancel").on_press(MainWindowMessage::TabBar(
+                        TabBarMessage::TabFileMessage(TabFileMessage::CancelAssignments)
+                    ))
+                ]
+                .spacing(10),
+            );
+        }
+
+        content.into()
+    }
+
+    /// Settings of the outline generated around the copper, replacing the outline file.
+    fn outline_generator(&self) -> Element<'_, MainWindowMessage> {
+        let on_shape_change = |shape| {
+            MainWindowMessage::TabBar(TabBarMessage::TabFileMessage(
+                TabFileMessage::OutlineShapeChange(shape),
+            ))
+        };
+        let shape = Some(self.generated_outline.shape);
+
+        column![
+            row![
+                radio(
+                    "Bounding box",
+                    OutlineShape::BoundingBox,
+                    shape,
+                    on_shape_change
+                ),
+                radio(
+                    "Convex hull",
+                    OutlineShape::ConvexHull,
+                    shape,
+                    on_shape_change
+                )
+            ]
+            .spacing(10),
+            row![
+                "Margin (mm)",
+                number_input(&self.generated_outline.margin, 0.0..=100.0, |x| {
+                    MainWindowMessage::TabBar(TabBarMessage::TabFileMessage(
+                        TabFileMessage::OutlineMarginChange(x),
+                    ))
+                })
+                .step(0.5),
+                "Corner radius (mm)",
+                number_input(&self.generated_outline.corner_radius, 0.0..=100.0, |x| {
+                    MainWindowMessage::TabBar(TabBarMessage::TabFileMessage(
+                        TabFileMessage::OutlineRadiusChange(x),
+                    ))
+                })
+                .step(0.5),
+                button("Generate outline").on_press(MainWindowMessage::TabBar(
+                    TabBarMessage::TabFileMessage(TabFileMessage::GenerateOutline)
+                ))
+            ]
+            .spacing(10)
+            .align_y(iced::Alignment::Center),
         ]
         .spacing(5)
+        .padding(padding::left(20))
         .into()
     }
 
-    async fn load_file(file_path: PathBuf, layer: CanvasLayer) -> Option<Layer> {
+    fn picker(&mut self, canvas_layer: &CanvasLayer) -> &mut FilePicker {
+        match canvas_layer {
+            CanvasLayer::Top => &mut self.top_file_picker,
+            CanvasLayer::Bottom => &mut self.bot_file_picker,
+            CanvasLayer::Drill => &mut self.drill_file_picker,
+            CanvasLayer::Outline => &mut self.outline_file_picker,
+        }
+    }
+
+    /// Fill the pickers with the confirmed files of a folder, archive or job and load them.
+    fn load_assignments(&mut self) -> Task<MainWindowMessage> {
+        let mut task = Task::none();
+
+        for canvas_layer in [
+            CanvasLayer::Top,
+            CanvasLayer::Bottom,
+            CanvasLayer::Drill,
+            CanvasLayer::Outline,
+        ] {
+            let files: Vec<PathBuf> = self
+                .assignments
+                .iter()
+                .filter(|(_, assignment)| assignment.0.as_ref() == Some(&canvas_layer))
+                .map(|(file, _)| file.clone())
+                .collect();
+
+            if files.is_empty() {
+                continue;
+            }
+
+            if canvas_layer == CanvasLayer::Top && self.pcb_sides == PcbSides::OneSide {
+                info!("Top layer file found, switching to 2-sides PCB");
+                self.pcb_sides = PcbSides::TwoSide;
+                self.top_file_picker.enable(true);
+            }
+
+            self.picker(&canvas_layer).set_files(files.clone());
+            task = task.chain(Self::load_task(files, canvas_layer));
+        }
+
+        self.assignments.clear();
+        task
+    }
+
+    fn load_task(file_paths: Vec<PathBuf>, canvas_layer: CanvasLayer) -> Task<MainWindowMessage> {
+        Task::done(MainWindowMessage::ShowLoading)
+            .chain(
+                Task::future(Self::load_file(file_paths, canvas_layer.clone())).then(move |x| {
+                    match x {
+                        Ok((new_layer, drill)) => {
+                            info!("Load new file to {:?} layer", canvas_layer.clone());
+                            let load = Task::done(MainWindowMessage::GerberCanvas(
+                                GerberCanvasMessage::LoadLayer(canvas_layer.clone(), new_layer),
+                            ));
+
+                            match drill {
+                                Some(drill) => load.chain(Self::drill_file_loaded(Some(drill))),
+                                None => load,
+                            }
+                        }
+                        Err(e) => {
+                            error!("Failed to load file: {e}");
+                            Task::done(MainWindowMessage::TabBar(TabBarMessage::TabFileMessage(
+                                TabFileMessage::LoadFailed(canvas_layer.clone(), e),
+                            )))
+                        }
+                    }
+                }),
+            )
+            .chain(Task::done(MainWindowMessage::HideLoading))
+    }
+
+    fn drill_file_loaded(drill: Option<DrillFile>) -> Task<MainWindowMessage> {
+        Task::done(MainWindowMessage::TabBar(TabBarMessage::Drilling(
+            TabDrillingMessage::DrillFileLoaded(drill),
+        )))
+    }
+
+    /// The layer to draw, and the drill model when it is a drill file. Drill files (PTH
+    /// and NPTH, Excellon or Gerber) are merged into one, a Gerber layer is read from the
+    /// first file. The outline is the board shape rather than its drawn lines.
+    async fn load_file(
+        file_paths: Vec<PathBuf>,
+        layer: CanvasLayer,
+    ) -> Result<(Layer, Option<DrillFile>), LayerError> {
+        let color = layer.color();
+
+        if layer == CanvasLayer::Drill {
+            let mut drill = DrillFile::default();
+            for file_path in file_paths.iter() {
+                let reader = Self::open_file(file_path)?;
+                drill.merge(if Self::is_gerber(file_path) {
+                    drill_from_gerber(&Self::parse_gerber(reader)?)?
+                } else {
+                    parse_excellon(reader)?
+                });
+            }
+
+            info!(
+                "{} holes and {} slots read",
+                drill.hole_count(),
+                drill.slot_count()
+            );
+            let layer = Layer::from_drill(&drill, color, LayerOptions::default());
+            return Ok((layer, Some(drill)));
+        }
+
+        let file_path = file_paths
+            .first()
+            .ok_or_else(|| LayerError::Io("No file selected".to_string()))?;
+        let gerber = Self::parse_gerber(Self::open_file(file_path)?)?;
+        let layer = if layer == CanvasLayer::Outline {
+            Layer::from_outline(&gerber, color, LayerOptions::default())?
+        } else {
+            Layer::from_gerber(&gerber, color, LayerOptions::default())?
+        };
+
+        let attributes = layer.attributes();
+        if let Some(function) = &attributes.function {
+            info!("File function: {function:?}");
+        }
+        if attributes.polarity == Some(FilePolarity::Negative) {
+            warn!("Negative file polarity, the layer is drawn as positive");
+        }
+
+        Ok((layer, None))
+    }
+
+    fn open_file(file_path: &Path) -> Result<BufReader<Box<dyn Read + Send>>, LayerError> {
         info!("Parsing {}", file_path.to_str().unwrap_or("unknown"));
-        let reader = BufReader::new(File::open(&file_path).ok()?);
-        match layer {
-            CanvasLayer::Top => Some(Layer::from_gerber(
-                &parse_gerber(reader),
-                Color::from_rgb(0.0, 0.0, 1.0),
-            )),
-            CanvasLayer::Bottom => Some(Layer::from_gerber(
-                &parse_gerber(reader),
-                Color::from_rgb(1.0, 0.0, 0.0),
-            )),
-            CanvasLayer::Outline => Some(Layer::from_gerber(
-                &parse_gerber(reader),
-                Color::from_rgb(0.0, 1.0, 0.0),
-            )),
-            CanvasLayer::Drill => None,
+        open_source(file_path)
+    }
+
+    /// Files of a folder picked by the user, each with the layer it seems to belong to.
+    async fn scan_folder() -> Vec<(PathBuf, Assignment)> {
+        info!("Open folder explorer");
+        let Some(folder) = rfd::FileDialog::new().pick_folder() else {
+            return Vec::new();
+        };
+
+        let mut files: Vec<PathBuf> = match fs::read_dir(&folder) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.is_file())
+                .collect(),
+            Err(e) => {
+                error!("Cannot read folder {}: {e}", folder.display());
+                return Vec::new();
+            }
+        };
+        files.sort();
+
+        Self::assign(
+            files
+                .into_iter()
+                .map(|file| {
+                    let canvas_layer = classify_file(&file);
+                    (file, canvas_layer)
+                })
+                .collect(),
+        )
+    }
+
+    /// Entries of a zip archive picked by the user, each with the layer it seems to belong
+    /// to. They are read from the archive when loaded.
+    async fn scan_archive() -> Vec<(PathBuf, Assignment)> {
+        info!("Open file explorer");
+        let Some(archive) = rfd::FileDialog::new()
+            .add_filter("Zip archive", &["zip", "ZIP"])
+            .pick_file()
+        else {
+            return Vec::new();
+        };
+
+        match classify_archive(&archive) {
+            Ok(entries) => Self::assign(entries),
+            Err(e) => {
+                error!("Cannot read archive: {e}");
+                Vec::new()
+            }
         }
     }
 
+    async fn pick_job() -> Option<GerberJob> {
+        info!("Open file explorer");
+        let path = rfd::FileDialog::new()
+            .add_filter("Gerber job", &["gbrjob"])
+            .pick_file()?;
+
+        match read_job(&path) {
+            Ok(job) => {
+                info!("Job file lists {} files", job.files.len());
+                Some(job)
+            }
+            Err(e) => {
+                error!("Cannot read job file: {e}");
+                None
+            }
+        }
+    }
+
+    /// Only the first file found for a Gerber layer is kept, drill files are merged.
+    fn assign(files: Vec<(PathBuf, Option<CanvasLayer>)>) -> Vec<(PathBuf, Assignment)> {
+        let mut assigned: Vec<CanvasLayer> = Vec::new();
+        files
+            .into_iter()
+            .map(|(file, canvas_layer)| {
+                let canvas_layer = match canvas_layer {
+                    Some(canvas_layer)
+                        if canvas_layer != CanvasLayer::Drill
+                            && assigned.contains(&canvas_layer) =>
+                    {
+                        warn!(
+                            "{} also looks like a {:?} file, it is ignored",
+                            file.display(),
+                            canvas_layer
+                        );
+                        None
+                    }
+                    canvas_layer => canvas_layer,
+                };
+
+                if let Some(canvas_layer) = &canvas_layer {
+                    assigned.push(canvas_layer.clone());
+                }
+
+                (file, Assignment(canvas_layer))
+            })
+            .collect()
+    }
+
+    fn is_gerber(file_path: &Path) -> bool {
+        file_path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("gbr"))
+    }
+
+    fn parse_gerber<R: Read>(reader: BufReader<R>) -> Result<GerberDoc, LayerError> {
+        gerber_parser::parse(reader).map_err(|(_, e)| match e {
+            ParseError::IoError(message) => LayerError::Io(message),
+            e => LayerError::Parse {
+                line: None,
+                message: e.to_string(),
+            },
+        })
+    }
+
     fn vec_str(vec: Vec<&str>) -> Vec<String> {
         vec.iter().map(ToString::to_string).collect()
     }
@@ -200,6 +656,8 @@ impl Default for Files {
     fn default() -> Self {
         let mut result = Self {
             pcb_sides: Default::default(),
+            assignments: Vec::new(),
+            generated_outline: GeneratedOutline::default(),
             top_file_picker: FilePicker::new(
                 None,
                 "Gerber File".to_string(),
@@ -212,8 +670,8 @@ impl Default for Files {
             ),
             drill_file_picker: FilePicker::new(
                 None,
-                "Excellon file".to_string(),
-                Self::vec_str(vec!["drl", "DRL", "txt", "TXT"]),
+                "Drill files".to_string(),
+                Self::vec_str(vec!["drl", "DRL", "txt", "TXT", "gbr", "GBR"]),
             ),
             outline_file_picker: FilePicker::new(
                 None,
@@ -223,6 +681,7 @@ impl Default for Files {
         };
 
         result.top_file_picker.enable(false);
+        result.drill_file_picker.multiple(true);
 
         result
     }
@@ -1,17 +1,254 @@
-use iced::{widget::text, Element};
-use iced_aw::TabLabel;
+use iced::{
+    padding,
+    widget::{button, column, radio, row, text, Column},
+    Alignment, Element, Task,
+};
+use iced_aw::{helpers::number_input, TabLabel};
 
-use crate::ui::message::MainWindowMessage;
+use crate::{
+    milling::{
+        clearing::{ClearingOptions, ClearingPattern},
+        isolation::{IsolationMode, IsolationOptions, MAX_PASSES},
+        toolpath::{MAX_OVERLAP, MIN_TOOL_DIAMETER},
+    },
+    ui::message::{GerberCanvasMessage, MainWindowMessage, TabBarMessage},
+};
+
+#[derive(Debug, Clone)]
+pub enum TabMillingMessage {
+    IsolationModeChange(IsolationMode),
+    ToolDiameterChange(usize, f64),
+    AddTool,
+    RemoveTool(usize),
+    PassesChange(u32),
+    OverlapChange(f64),
+    GenerateIsolation,
+    ClearingPatternChange(ClearingPattern),
+    ClearingToolDiameterChange(f64),
+    ClearingOverlapChange(f64),
+    ClearingMarginChange(f64),
+    GenerateClearing,
+}
 
 #[derive(Debug, Default)]
-pub struct Milling {}
+pub struct Milling {
+    isolation: IsolationOptions,
+    clearing: ClearingOptions,
+    board_thickness: Option<f64>,
+}
 
 impl Milling {
     pub fn tab_label(&self) -> TabLabel {
         TabLabel::Text("Milling".to_string())
     }
 
-    pub fn view(&self) -> Element<MainWindowMessage> {
-        text("Milling panel").into()
+    pub fn update(&mut self, message: TabMillingMessage) -> Task<MainWindowMessage> {
+        match message {
+            TabMillingMessage::IsolationModeChange(mode) => {
+                self.isolation.mode = mode;
+                Task::none()
+            }
+            TabMillingMessage::ToolDiameterChange(index, diameter) => {
+                if let Some(tool) = self.isolation.tool_diameters.get_mut(index) {
+                    *tool = diameter;
+                }
+                Task::none()
+            }
+            TabMillingMessage::AddTool => {
+                // Rest machining goes from large tools to smaller ones
+                if let Some(last) = self.isolation.tool_diameters.last() {
+                    let diameter = (last / 2.0).max(MIN_TOOL_DIAMETER);
+                    self.isolation.tool_diameters.push(diameter);
+                }
+                Task::none()
+            }
+            TabMillingMessage::RemoveTool(index) => {
+                if index < self.isolation.tool_diameters.len()
+                    && self.isolation.tool_diameters.len() > 1
+                {
+                    self.isolation.tool_diameters.remove(index);
+                }
+                Task::none()
+            }
+            TabMillingMessage::PassesChange(passes) => {
+                self.isolation.passes = passes;
+                Task::none()
+            }
+            TabMillingMessage::OverlapChange(overlap) => {
+                self.isolation.overlap = overlap;
+                Task::none()
+            }
+            TabMillingMessage::GenerateIsolation => Task::done(MainWindowMessage::GerberCanvas(
+                GerberCanvasMessage::GenerateIsolation(self.isolation.clone()),
+            )),
+            TabMillingMessage::ClearingPatternChange(pattern) => {
+                self.clearing.pattern = pattern;
+                Task::none()
+            }
+            TabMillingMessage::ClearingToolDiameterChange(diameter) => {
+                self.clearing.tool_diameter = diameter;
+                Task::none()
+            }
+            TabMillingMessage::ClearingOverlapChange(overlap) => {
+                self.clearing.overlap = overlap;
+                Task::none()
+            }
+            TabMillingMessage::ClearingMarginChange(margin) => {
+                self.clearing.margin = margin;
+                Task::none()
+            }
+            TabMillingMessage::GenerateClearing => Task::done(MainWindowMessage::GerberCanvas(
+                GerberCanvasMessage::GenerateClearing(self.clearing),
+            )),
+        }
+    }
+
+    pub fn isolation(&self) -> IsolationOptions {
+        self.isolation.clone()
+    }
+
+    pub fn set_isolation(&mut self, isolation: IsolationOptions) {
+        self.isolation = isolation;
+    }
+
+    pub fn clearing(&self) -> ClearingOptions {
+        self.clearing
+    }
+
+    pub fn set_clearing(&mut self, clearing: ClearingOptions) {
+        self.clearing = clearing;
+    }
+
+    /// Cutout depth default, going through the board.
+    pub fn set_board_thickness(&mut self, thickness: f64) {
+        self.board_thickness = Some(thickness);
+    }
+
+    pub fn view(&self) -> Element<'_, MainWindowMessage> {
+        let on_mode_change = |mode| {
+            MainWindowMessage::TabBar(TabBarMessage::Milling(
+                TabMillingMessage::IsolationModeChange(mode),
+            ))
+        };
+        let mode = Some(self.isolation.mode);
+        let on_pattern_change = |pattern| {
+            MainWindowMessage::TabBar(TabBarMessage::Milling(
+                TabMillingMessage::ClearingPatternChange(pattern),
+            ))
+        };
+        let pattern = Some(self.clearing.pattern);
+
+        let mut tools = Column::new().spacing(5);
+        for (index, diameter) in self.isolation.tool_diameters.iter().enumerate() {
+            let mut tool = row![
+                text(format!("Tool {} diameter (mm)", index + 1)),
+                number_input(diameter, MIN_TOOL_DIAMETER..=5.0, move |x| {
+                    MainWindowMessage::TabBar(TabBarMessage::Milling(
+                        TabMillingMessage::ToolDiameterChange(index, x),
+                    ))
+                })
+                .step(0.05),
+            ]
+            .spacing(10)
+            .align_y(Alignment::Center);
+
+            if self.isolation.tool_diameters.len() > 1 {
+                tool = tool.push(button("Remove").on_press(MainWindowMessage::TabBar(
+                    TabBarMessage::Milling(TabMillingMessage::RemoveTool(index)),
+                )));
+            }
+            tools = tools.push(tool);
+        }
+        tools = tools.push(
+            button("Add a smaller tool").on_press(MainWindowMessage::TabBar(
+                TabBarMessage::Milling(TabMillingMessage::AddTool),
+            )),
+        );
+
+        column![
+            "Isolation",
+            row![
+                radio("Offset", IsolationMode::Offset, mode, on_mode_change),
+                radio("Voronoi", IsolationMode::Voronoi, mode, on_mode_change)
+            ]
+            .spacing(10)
+            .padding(padding::left(20)),
+            tools,
+            row![
+                "Passes",
+                number_input(&self.isolation.passes, 1..=MAX_PASSES, |x| {
+                    MainWindowMessage::TabBar(TabBarMessage::Milling(
+                        TabMillingMessage::PassesChange(x),
+                    ))
+                }),
+                "Overlap",
+                number_input(&self.isolation.overlap, 0.0..=MAX_OVERLAP, |x| {
+                    MainWindowMessage::TabBar(TabBarMessage::Milling(
+                        TabMillingMessage::OverlapChange(x),
+                    ))
+                })
+                .step(0.05),
+                button("Generate isolation").on_press(MainWindowMessage::TabBar(
+                    TabBarMessage::Milling(TabMillingMessage::GenerateIsolation)
+                ))
+            ]
+            .spacing(10)
+            .align_y(Alignment::Center),
+            "Copper clearing",
+            row![
+                radio(
+                    "Concentric",
+                    ClearingPattern::Concentric,
+                    pattern,
+                    on_pattern_change
+                ),
+                radio(
+                    "Zigzag",
+                    ClearingPattern::Zigzag,
+                    pattern,
+                    on_pattern_change
+                )
+            ]
+            .spacing(10)
+            .padding(padding::left(20)),
+            row![
+                "Tool diameter (mm)",
+                number_input(&self.clearing.tool_diameter, MIN_TOOL_DIAMETER..=5.0, |x| {
+                    MainWindowMessage::TabBar(TabBarMessage::Milling(
+                        TabMillingMessage::ClearingToolDiameterChange(x),
+                    ))
+                })
+                .step(0.05),
+                "Margin (mm)",
+                number_input(&self.clearing.margin, 0.0..=5.0, |x| {
+                    MainWindowMessage::TabBar(TabBarMessage::Milling(
+                        TabMillingMessage::ClearingMarginChange(x),
+                    ))
+                })
+                .step(0.05),
+            ]
+            .spacing(10)
+            .align_y(Alignment::Center),
+            row![
+                "Overlap",
+                number_input(&self.clearing.overlap, 0.0..=MAX_OVERLAP, |x| {
+                    MainWindowMessage::TabBar(TabBarMessage::Milling(
+                        TabMillingMessage::ClearingOverlapChange(x),
+                    ))
+                })
+                .step(0.05),
+                button("Generate clearing").on_press(MainWindowMessage::TabBar(
+                    TabBarMessage::Milling(TabMillingMessage::GenerateClearing)
+                ))
+            ]
+            .spacing(10)
+            .align_y(Alignment::Center),
+            text(match self.board_thickness {
+                Some(thickness) => format!("Cutout depth: {thickness:.2} mm"),
+                None => "Cutout depth: unknown board thickness".to_string(),
+            }),
+        ]
+        .spacing(5)
+        .into()
     }
 }